@@ -0,0 +1,194 @@
+use chrono::NaiveDate;
+use clap::Parser;
+use colored::Colorize;
+use toml::Table;
+
+use crate::tools::files::format_size;
+use crate::tools::history::HistoryEntry;
+
+use super::mail::Mail;
+use super::MyCommand;
+
+/// 历史记录文件的位置与 mail 命令共用 `[mail]` 中的配置
+const TABLE_NAME: &str = "mail";
+
+#[derive(Parser, Debug)]
+/// 查看提交历史
+/// 每次使用 mail 命令发送成功后记录 Message-ID、收件人、主题、附件和课程
+pub struct History {
+    /// 显示一条记录的详细信息，可以是列表中的序号或者 Message-ID 的一部分
+    pub id: Option<String>,
+    #[arg(short, long)]
+    /// 只列出指定课程序号的提交
+    pub course: Option<i64>,
+    #[arg(long)]
+    /// 只列出发送给包含这个地址的收件人的提交
+    pub to: Option<String>,
+    #[arg(long)]
+    /// 只列出这一天(包含)之后的提交，例如: 2024-05-20
+    pub since: Option<String>,
+    #[arg(short = 'n', long)]
+    /// 只列出最近的几条提交
+    pub limit: Option<i64>,
+}
+
+impl MyCommand for &History {
+    fn run(&self, config_obj: &toml::Table) {
+        let filed_map = self.parse_field(config_obj);
+        let history = Mail::history_file(&filed_map);
+        let entries = match history.entries() {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!(
+                    "{} {}: {}",
+                    "Error 读取提交历史失败".red(),
+                    history.path.display(),
+                    err
+                );
+                std::process::exit(1);
+            }
+        };
+        match filed_map.get("id") {
+            Some(id) => History::show(&entries, id.as_str().unwrap()),
+            None => History::list(&entries, &filed_map),
+        }
+    }
+    fn get_global_filed_map(&self, config_obj: &Table) -> Table {
+        super::get_global_filed_map(config_obj)
+    }
+
+    fn get_filed_map(&self, config_obj: &Table) -> Table {
+        match config_obj.get(TABLE_NAME) {
+            Some(table) => {
+                let table = table.as_table().unwrap();
+                table.clone()
+            }
+            None => Table::new(),
+        }
+    }
+}
+
+impl IntoIterator for &History {
+    type Item = (String, toml::Value);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+    fn into_iter(self) -> Self::IntoIter {
+        let mut vec = Vec::new();
+        if let Some(id) = &self.id {
+            vec.push(("id".to_string(), toml::Value::from(id.as_str())));
+        }
+        if let Some(course) = self.course {
+            vec.push(("course".to_string(), toml::Value::from(course)));
+        }
+        if let Some(to) = &self.to {
+            vec.push(("recipient".to_string(), toml::Value::from(to.as_str())));
+        }
+        if let Some(since) = &self.since {
+            vec.push(("since".to_string(), toml::Value::from(since.as_str())));
+        }
+        if let Some(limit) = self.limit {
+            vec.push(("limit".to_string(), toml::Value::from(limit)));
+        }
+        vec.into_iter()
+    }
+}
+
+impl History {
+    /// 按条件筛选后列出提交，序号为记录在历史中的位置，从 1 开始
+    fn list(entries: &[HistoryEntry], filed_map: &Table) {
+        let course = filed_map.get("course").map(|v| v.as_integer().unwrap());
+        // 与 [mail].to 区分 命令行的 --to 使用 recipient 字段
+        let to = filed_map.get("recipient").map(|v| v.as_str().unwrap());
+        let since = filed_map.get("since").map(|v| {
+            let since = v.as_str().unwrap();
+            NaiveDate::parse_from_str(since, "%Y-%m-%d")
+                .or_else(|_| NaiveDate::parse_from_str(since, "%Y%m%d"))
+                .unwrap_or_else(|_| {
+                    eprintln!("{} {}", "Error 日期格式错误，例如 2024-05-20:".red(), since);
+                    std::process::exit(1);
+                })
+        });
+        let mut matched = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| course.is_none() || entry.courses_number == course)
+            .filter(|(_, entry)| {
+                to.is_none_or(|to| entry.recipients.iter().any(|r| r.contains(to)))
+            })
+            .filter(|(_, entry)| {
+                since.is_none_or(|since| entry.sent_time().is_some_and(|t| t.date_naive() >= since))
+            })
+            .collect::<Vec<_>>();
+        if let Some(limit) = filed_map.get("limit").map(|v| v.as_integer().unwrap()) {
+            let skip = matched.len().saturating_sub(limit.max(0) as usize);
+            matched.drain(..skip);
+        }
+        if matched.is_empty() {
+            println!("没有提交记录");
+            return;
+        }
+        for (index, entry) in &matched {
+            let time = entry
+                .sent_time()
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| entry.sent_at.clone());
+            let attachments = entry
+                .attachments
+                .iter()
+                .map(|attachment| attachment.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ");
+            println!(
+                "{:>4}  {}  {}  {}  -> {}",
+                index + 1,
+                time,
+                entry.course().unwrap_or_else(|| "-".to_string()).green(),
+                attachments,
+                entry.recipients.join(", ")
+            );
+        }
+        println!("共 {} 条提交", matched.len());
+    }
+
+    /// 显示一条提交的详细信息
+    fn show(entries: &[HistoryEntry], id: &str) {
+        let found = match id.parse::<usize>() {
+            Ok(index) => entries.get(index.wrapping_sub(1)).into_iter().collect(),
+            Err(_) => entries
+                .iter()
+                .filter(|entry| entry.message_id.contains(id))
+                .collect::<Vec<&HistoryEntry>>(),
+        };
+        let entry = match found.as_slice() {
+            [entry] => entry,
+            [] => {
+                eprintln!("{} {}", "Error 没有这条提交记录:".red(), id);
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!(
+                    "{} {}",
+                    "Error 匹配到多条提交记录，请写出更完整的 Message-ID:".red(),
+                    id
+                );
+                std::process::exit(1);
+            }
+        };
+        println!("Message-ID: {}", entry.message_id);
+        println!("发送时间: {}", entry.sent_at);
+        println!("主题: {}", entry.subject);
+        println!("发件人: {}", entry.from);
+        println!("收件人: {}", entry.recipients.join(", "));
+        if let Some(course) = entry.course() {
+            println!("课程: {}", course);
+        }
+        println!("附件:");
+        for attachment in &entry.attachments {
+            println!(
+                "  {} ({})  sha256: {}",
+                attachment.name,
+                format_size(attachment.size),
+                attachment.sha256
+            );
+        }
+    }
+}
@@ -1,35 +1,128 @@
-use std::{fs, io::Write, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-use crate::config::get_default_zip_file_name;
-use base64::Engine;
 use chrono::Local;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use colored::Colorize;
-use lettre::message::{header, Body, MultiPart, SinglePart};
-use lettre::transport::smtp::authentication::Credentials;
-use lettre::{Message, SmtpTransport, Transport};
+use lettre::message::{header, Body, Mailbox, MultiPart, SinglePart};
+use lettre::message::MessageBuilder;
+use lettre::Message;
+use crate::config::{
+    config_dir, get_name_template, render_zip_file_name, DEFAULT_COURSE_NAME_TEMPLATE,
+};
 use crate::tools::date::Date;
-use super::zip::Zip;
+use crate::tools::eml::{
+    decode_words, remove_header, AttachmentContentType, AttachmentDisposition, Eml,
+};
+use crate::tools::files::{format_size, parse_size, sha256_file, TempDir};
+use crate::tools::history::{History, HistoryAttachment, HistoryEntry};
+use crate::tools::manifest::read_manifest_file;
+use crate::tools::markdown;
+use crate::tools::outbox::{Outbox, OutboxItem, OutboxMeta};
+use crate::tools::smtp::{self, Security, SmtpSettings};
+use super::new::{find_project, parse_project_name};
+use super::zip::{self, Zip};
 use super::MyCommand;
 
 const TABLE_NAME: &str = "mail";
+/// 附件总大小的默认限制 QQ 邮箱普通附件的上限
+const DEFAULT_MAX_ATTACHMENT_SIZE: u64 = 50 * 1024 * 1024;
+
+/// 每封邮件默认最多尝试发送的次数
+const DEFAULT_RETRIES: i64 = 3;
+/// 发件箱默认位于配置文件所在的文件夹
+const DEFAULT_OUTBOX_DIR: &str = "outbox";
+/// 提交历史默认位于配置文件所在的文件夹
+const DEFAULT_HISTORY_FILE: &str = "history.toml";
+/// 重交时邮件主题默认的前缀
+const DEFAULT_RESUBMIT_TAG: &str = "[重交 v{version}]";
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 /// 发送邮件的命令
 /// 可以生成本地邮件文件 和发送邮件，并支持自动压缩,自动发送
 pub struct Mail {
     #[arg(long, short)]
     /// 是否发送邮件
     pub send: bool,
+    #[arg(long, conflicts_with_all = ["send", "auto", "output"])]
+    /// 检查 smtp 服务器的连接、加密和认证，不发送邮件
+    pub check: bool,
     #[arg(long, short)]
     /// 自动打包，自动发送，一键完成
     pub auto: bool,
     #[arg(long, short = 'f')]
-    /// 附件路径 可选
-    pub attachment: Option<String>,
+    /// 附件路径 可选，可以重复使用，支持通配符，文件夹会先压缩再作为附件
+    /// 例如: -f 笔记.md -f "截图/*.png" -f ../12-虚基类
+    pub attachment: Option<Vec<String>>,
     #[arg(long, short)]
     /// 将邮件生成的原始信息输出到文件
     pub output: Option<String>,
+    #[arg(long, conflicts_with = "message")]
+    /// 邮件正文的 Markdown 文件，覆盖配置中的 body_template
+    pub body_file: Option<String>,
+    #[arg(long, short)]
+    /// 邮件正文(Markdown)，覆盖配置中的 body_template
+    pub message: Option<String>,
+    #[arg(long)]
+    /// 收件人，可以重复使用，例如: --to 张老师<teacher@qq.com> --to ta@qq.com
+    pub to: Option<Vec<String>>,
+    #[arg(long)]
+    /// 抄送，可以重复使用
+    pub cc: Option<Vec<String>>,
+    #[arg(long)]
+    /// 密送，可以重复使用，例如密送给自己留底
+    pub bcc: Option<Vec<String>>,
+    #[arg(long)]
+    /// 回复地址，可以重复使用
+    pub reply_to: Option<Vec<String>>,
+    #[arg(long)]
+    /// 附件与之前的提交完全相同时不再确认，直接发送
+    pub force: bool,
+    #[command(subcommand)]
+    pub action: Option<MailAction>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MailAction {
+    /// 重新发送发件箱中的邮件，失败时等待一段时间后重试
+    Flush {
+        /// 只发送指定的邮件，名称可以只写开头的一部分，默认发送全部
+        names: Vec<String>,
+        #[arg(long)]
+        /// 每封邮件最多尝试的次数，默认为 3
+        retries: Option<i64>,
+    },
+    /// 发送之前生成的 eml 文件，邮件内容原样发送，发送成功后写入提交历史
+    SendEml {
+        /// eml 文件路径，例如 mail -o 生成的 mail.eml
+        file: String,
+        #[arg(long)]
+        /// 信封中的发件人，默认使用邮件头中的 From
+        from: Option<String>,
+        #[arg(long)]
+        /// 信封中的收件人，可以重复使用，默认使用邮件头中的 To、Cc 和 Bcc
+        to: Option<Vec<String>>,
+    },
+    /// 列出发件箱中等待发送的邮件，指定名称时显示这封邮件的详细信息
+    Outbox {
+        /// 邮件名称，可以只写开头的一部分
+        name: Option<String>,
+    },
+}
+
+/// 收件人、抄送、密送以及回复地址
+struct Recipients {
+    to: Vec<Mailbox>,
+    cc: Vec<Mailbox>,
+    bcc: Vec<Mailbox>,
+    reply_to: Vec<Mailbox>,
 }
 
 impl IntoIterator for &Mail {
@@ -39,16 +132,56 @@ impl IntoIterator for &Mail {
     fn into_iter(self) -> Self::IntoIter {
         let mut map = std::collections::HashMap::new();
         map.insert("send".to_string(), toml::Value::Boolean(self.send));
+        map.insert("check".to_string(), toml::Value::Boolean(self.check));
         if let Some(attachment) = &self.attachment {
             map.insert(
                 "attachment".to_string(),
-                toml::Value::String(attachment.clone()),
+                toml::Value::Array(
+                    attachment
+                        .iter()
+                        .map(|path| toml::Value::from(path.as_str()))
+                        .collect(),
+                ),
             );
         }
         if let Some(output) = &self.output {
             map.insert("output".to_string(), toml::Value::String(output.clone()));
         }
         map.insert("auto".to_string(), toml::Value::Boolean(self.auto));
+        if self.force {
+            map.insert("force".to_string(), toml::Value::Boolean(true));
+        }
+        if let Some(body_file) = &self.body_file {
+            map.insert("body_file".to_string(), toml::Value::from(body_file.as_str()));
+        }
+        if let Some(message) = &self.message {
+            map.insert("message".to_string(), toml::Value::from(message.as_str()));
+        }
+        for (key, addresses) in [
+            ("to", &self.to),
+            ("cc", &self.cc),
+            ("bcc", &self.bcc),
+            ("reply_to", &self.reply_to),
+        ] {
+            if let Some(addresses) = addresses {
+                map.insert(
+                    key.to_string(),
+                    toml::Value::Array(
+                        addresses
+                            .iter()
+                            .map(|address| toml::Value::from(address.as_str()))
+                            .collect(),
+                    ),
+                );
+            }
+        }
+        if let Some(MailAction::Flush {
+            retries: Some(retries),
+            ..
+        }) = &self.action
+        {
+            map.insert("retries".to_string(), toml::Value::from(*retries));
+        }
         map.into_iter()
     }
 }
@@ -57,6 +190,18 @@ impl MyCommand for &Mail {
     fn run(&self, config_obj: &toml::Table) {
         let filed_map = self.parse_field(config_obj);
         // block_on(self.send(&filed_map, config_obj));
+        match &self.action {
+            Some(MailAction::Flush { names, .. }) => return self.flush(&filed_map, names),
+            Some(MailAction::Outbox { name }) => return self.outbox(&filed_map, name.as_deref()),
+            Some(MailAction::SendEml { file, from, to }) => {
+                return self.send_eml(&filed_map, config_obj, file, from.as_deref(), to.as_deref())
+            }
+            None => {}
+        }
+        if let Some(true) = filed_map.get("check").map(|check| check.as_bool().unwrap()) {
+            self.check(&filed_map);
+            return;
+        }
         self.send_with_lettre(&filed_map, config_obj);
     }
 
@@ -79,28 +224,427 @@ impl MyCommand for &Mail {
 impl Mail {
     // 使用lettre进行邮件构造和发送
     fn send_with_lettre(&self, field_map: &toml::Table, config_obj: &toml::Table) {
-        let message = self.build_message_with_lettre(field_map, config_obj);
-        let mailer = self.build_conntent_with_lettre(field_map);
-        // 如果需要写入文件 在此时写入
+        let is_send = match field_map.get("send") {
+            None => false,
+            Some(send) => send.as_bool().unwrap(),
+        };
+        // 发送时先检查 smtp 配置 避免打包之后才发现配置错误
+        let smtp = is_send.then(|| self.build_conntent_with_lettre(field_map));
+        let (message, history) = self.build_message_with_lettre(field_map, config_obj);
+        let message_data = message.formatted();
+        // 如果需要写入文件 在此时写入 文件中保留 Bcc 之后 send-eml 发送时才能找到密送的收件人
         match field_map.get("output") {
             None => {}
             Some(output) => {
                 println!("{} {output}", "输出到文件: ".blue());
-                let message_data = message.formatted();
                 self.output_to_file(output.as_str().unwrap(), &message_data);
             }
         }
 
-        let is_send = match field_map.get("send") {
-            None => false,
-            Some(send) => send.as_bool().unwrap(),
+        if let Some(smtp) = smtp {
+            let meta = OutboxMeta {
+                from: message
+                    .envelope()
+                    .from()
+                    .map(|from| from.to_string())
+                    .unwrap_or_default(),
+                to: message.envelope().to().iter().map(|to| to.to_string()).collect(),
+                subject: message.headers().get_raw("Subject").unwrap_or_default().to_string(),
+                message_id: message.headers().get_raw("Message-ID").map(|id| id.to_string()),
+                created_at: Local::now().to_rfc3339(),
+                history: Some(history),
+                ..Default::default()
+            };
+            let message_data = remove_header(&message_data, "Bcc");
+            Mail::send_through_outbox(&smtp, field_map, meta, &message_data);
+        }
+    }
+
+    /// `mail send-eml` 原样发送之前生成的 eml 文件
+    /// 信封根据邮件头中的 From、To、Cc、Bcc 生成，也可以使用 `--from` `--to` 指定
+    fn send_eml(
+        &self,
+        field_map: &toml::Table,
+        config_obj: &toml::Table,
+        file: &str,
+        from: Option<&str>,
+        to: Option<&[String]>,
+    ) {
+        let data = fs::read(file).unwrap_or_else(|err| {
+            eprintln!("{} {}: {}", "Error 读取邮件失败".red(), file, err);
+            std::process::exit(1);
+        });
+        let eml = Eml::parse(&data).unwrap_or_else(|err| {
+            eprintln!("{} {}: {}", "Error 解析邮件失败".red(), file, err);
+            std::process::exit(1);
+        });
+        let parse_mailbox = |address: &str| match address.parse::<Mailbox>() {
+            Ok(mailbox) => mailbox.email.to_string(),
+            Err(err) => {
+                eprintln!("{} {}: {}", "Error 邮箱地址格式错误".red(), address, err);
+                std::process::exit(1);
+            }
+        };
+        let Some(from) = from
+            .map(parse_mailbox)
+            .or_else(|| eml.addresses("From").into_iter().next())
+        else {
+            eprintln!("{}", "Error 邮件中没有发件人，请使用 --from 指定".red());
+            std::process::exit(1);
+        };
+        let to = to.map(|to| to.iter().map(|to| parse_mailbox(to)).collect());
+        let recipients = Mail::eml_recipients(&eml, to);
+        if recipients.is_empty() {
+            eprintln!("{}", "Error 邮件中没有收件人，请使用 --to 指定".red());
+            std::process::exit(1);
+        }
+        let subject = decode_words(eml.header("Subject").unwrap_or_default());
+        let message_id = eml
+            .header("Message-ID")
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty());
+        let mut meta = OutboxMeta {
+            from,
+            to: recipients,
+            subject,
+            message_id,
+            created_at: Local::now().to_rfc3339(),
+            ..Default::default()
+        };
+        if let Err(err) = meta.envelope() {
+            eprintln!("{} {}", "Error 无法生成信封:".red(), err);
+            std::process::exit(1);
+        }
+        println!("发件人: {}", meta.from);
+        println!("收件人: {}", meta.to.join(", "));
+        println!("主题: {}", meta.subject);
+        let smtp = self.build_conntent_with_lettre(field_map);
+
+        // 附件写入临时文件夹 与 mail 命令相同地记录附件的 SHA-256 和压缩包清单中的课程
+        let temp = TempDir::new("send-eml").unwrap();
+        let attachments = eml
+            .attachments
+            .iter()
+            .enumerate()
+            .map(|(index, (name, content))| {
+                let name = Path::new(name)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| format!("attachment-{}", index + 1));
+                // 每个附件一个文件夹 避免重名的附件互相覆盖
+                let path = temp.0.join(index.to_string()).join(name);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, content).unwrap();
+                path
+            })
+            .collect::<Vec<PathBuf>>();
+        // 邮件可能是在其他文件夹保存的 只使用附件压缩包清单中的课程 不使用当前所在的课程文件夹
+        let course = Mail::course_from_manifest(&attachments, config_obj);
+        let mut history = Mail::history_entry(&attachments, course);
+        // 邮件原样发送 无法补充 Message-ID 没有时不写入提交历史 避免之后重交时引用空的 Message-ID
+        match &meta.message_id {
+            Some(message_id) => {
+                history.message_id = message_id.clone();
+                history.from = meta.from.clone();
+                history.recipients = meta.to.clone();
+                history.subject = meta.subject.clone();
+                meta.history = Some(history);
+            }
+            None => println!(
+                "{} 邮件中没有 Message-ID，发送后不会写入提交历史",
+                "Warning".yellow()
+            ),
+        }
+
+        // Bcc 只用于生成信封 发送的邮件中删除 避免其他收件人看到密送地址
+        Mail::send_through_outbox(&smtp, field_map, meta, &remove_header(&data, "Bcc"));
+    }
+
+    /// send-eml 信封中的收件人，没有指定 `--to` 时使用邮件头中的 To、Cc 和 Bcc，重复的地址只保留一个
+    fn eml_recipients(eml: &Eml, to: Option<Vec<String>>) -> Vec<String> {
+        let mut recipients = match to {
+            Some(to) => to,
+            None => ["To", "Cc", "Bcc"]
+                .iter()
+                .flat_map(|name| eml.addresses(name))
+                .collect(),
+        };
+        let mut seen = BTreeSet::new();
+        recipients.retain(|recipient| seen.insert(recipient.to_lowercase()));
+        recipients
+    }
+
+    /// 先写入发件箱再发送，发送失败时可以使用 `mail flush` 重新发送
+    fn send_through_outbox(
+        smtp: &SmtpSettings,
+        field_map: &toml::Table,
+        meta: OutboxMeta,
+        message_data: &[u8],
+    ) {
+        let outbox = Mail::outbox_dir(field_map);
+        let mut item = match outbox.enqueue(meta, message_data) {
+            Ok(item) => item,
+            Err(err) => {
+                eprintln!("{} {}: {}", "Error 写入发件箱失败".red(), outbox.dir.display(), err);
+                std::process::exit(1);
+            }
+        };
+        let history = Mail::history_file(field_map);
+        if let Err(err) = Mail::deliver(smtp, &outbox, &history, &mut item) {
+            eprintln!("{} {}", "Error 发送邮件失败:".red(), err);
+            if let Some(hint) = smtp::hint(err.as_ref(), smtp) {
+                eprintln!("  {} {}", "提示:".yellow(), hint);
+            }
+            eprintln!(
+                "邮件已保存到发件箱: {}\n可以使用 cr-cli mail flush 重新发送",
+                item.eml.display()
+            );
+            std::process::exit(1);
+        }
+        println!("{}", "发送邮件成功！".green());
+    }
+
+    /// 发件箱文件夹 `[mail].outbox_dir`，默认为配置文件所在文件夹中的 outbox
+    fn outbox_dir(field_map: &toml::Table) -> Outbox {
+        let dir = match field_map.get("outbox_dir").map(|v| v.as_str().unwrap()) {
+            // 相对路径相对于配置文件所在的文件夹 不随当前所在的文件夹变化
+            Some(dir) if !dir.is_empty() => config_dir().join(dir),
+            _ => config_dir().join(DEFAULT_OUTBOX_DIR),
         };
-        if is_send {
-            mailer.send(&message).unwrap();
-            println!("{}", "发送邮件成功！".green());
+        Outbox::new(&dir)
+    }
+
+    /// 提交历史文件 `[mail].history_file`，相对路径与发件箱相同，默认为配置文件所在文件夹中的 history.toml
+    pub fn history_file(field_map: &toml::Table) -> History {
+        let path = match field_map.get("history_file").map(|v| v.as_str().unwrap()) {
+            Some(path) if !path.is_empty() => config_dir().join(path),
+            _ => config_dir().join(DEFAULT_HISTORY_FILE),
+        };
+        History::new(&path)
+    }
+
+    /// 发送发件箱中的一封邮件，成功后移动到 sent 文件夹并写入提交历史，失败时记录错误
+    fn deliver(
+        smtp: &SmtpSettings,
+        outbox: &Outbox,
+        history: &History,
+        item: &mut OutboxItem,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let data = fs::read(&item.eml)?;
+        let envelope = item.meta.envelope()?;
+        match smtp.send(&envelope, &data) {
+            Ok(_) => {
+                let sent = outbox.mark_sent(item)?;
+                if let Some(entry) = item
+                    .meta
+                    .history
+                    .as_mut()
+                    .filter(|entry| !entry.message_id.is_empty())
+                {
+                    entry.sent_at = Local::now().to_rfc3339();
+                    // 邮件已经发送成功 写入历史失败时只提示
+                    if let Err(err) = history.append(entry) {
+                        eprintln!(
+                            "{} {}: {}",
+                            "Warning 写入提交历史失败".yellow(),
+                            history.path.display(),
+                            err
+                        );
+                    }
+                }
+                Ok(sent)
+            }
+            Err(err) => {
+                outbox.record_failure(item, &err.to_string())?;
+                Err(err)
+            }
+        }
+    }
+
+    /// `mail flush` 逐个重新发送发件箱中的邮件，失败时等待 2 4 8 ... 秒后重试
+    /// 服务器明确拒绝(5xx)的邮件不再重试
+    fn flush(&self, field_map: &toml::Table, names: &[String]) {
+        let smtp = self.build_conntent_with_lettre(field_map);
+        let outbox = Mail::outbox_dir(field_map);
+        let history = Mail::history_file(field_map);
+        let items = match names.is_empty() {
+            true => outbox.list(),
+            false => names
+                .iter()
+                .map(|name| {
+                    outbox.find(name).unwrap_or_else(|err| {
+                        eprintln!("{} {}", "Error".red(), err);
+                        std::process::exit(1);
+                    })
+                })
+                .collect(),
+        };
+        if items.is_empty() {
+            println!("发件箱中没有等待发送的邮件");
+            return;
+        }
+        let retries = match field_map.get("retries") {
+            Some(retries) => match retries.as_integer().filter(|retries| *retries > 0) {
+                Some(retries) => retries,
+                None => {
+                    eprintln!("{} {}", "Error retries 必须是正整数:".red(), retries);
+                    std::process::exit(1);
+                }
+            },
+            None => DEFAULT_RETRIES,
+        };
+        let mut failed = 0;
+        for mut item in items {
+            println!("发送 {} ({})", item.name, item.meta.to.join(", "));
+            for attempt in 1..=retries {
+                let err = match Mail::deliver(&smtp, &outbox, &history, &mut item) {
+                    Ok(_) => {
+                        println!("{}", "发送成功".green());
+                        break;
+                    }
+                    Err(err) => err,
+                };
+                eprintln!(
+                    "{} 第 {} 次发送失败: {}",
+                    "Warning".yellow(),
+                    attempt,
+                    err
+                );
+                let is_permanent = err
+                    .downcast_ref::<lettre::transport::smtp::Error>()
+                    .is_some_and(|err| err.is_permanent());
+                if is_permanent || attempt == retries {
+                    if let Some(hint) = smtp::hint(err.as_ref(), &smtp) {
+                        eprintln!("  {} {}", "提示:".yellow(), hint);
+                    }
+                    failed += 1;
+                    break;
+                }
+                let delay = Duration::from_secs(2u64.pow(attempt as u32).min(60));
+                println!("{} 秒后重试", delay.as_secs());
+                std::thread::sleep(delay);
+            }
+        }
+        if failed > 0 {
+            eprintln!("{} {} 封邮件发送失败，仍保留在发件箱中", "Error".red(), failed);
+            std::process::exit(1);
         }
     }
 
+    /// `mail outbox` 列出发件箱中的邮件，或者显示一封邮件的投递信息和邮件头
+    fn outbox(&self, field_map: &toml::Table, name: Option<&str>) {
+        let outbox = Mail::outbox_dir(field_map);
+        let Some(name) = name else {
+            let items = outbox.list();
+            println!("发件箱: {}", outbox.dir.display());
+            if items.is_empty() {
+                println!("没有等待发送的邮件");
+                return;
+            }
+            for item in &items {
+                println!(
+                    "{}  收件人: {}  已尝试 {} 次",
+                    item.name,
+                    item.meta.to.join(", "),
+                    item.meta.attempts
+                );
+                if let Some(last_error) = &item.meta.last_error {
+                    println!("  {} {}", "最后的错误:".yellow(), last_error);
+                }
+            }
+            println!("共 {} 封邮件，使用 cr-cli mail flush 重新发送", items.len());
+            return;
+        };
+        let item = outbox.find(name).unwrap_or_else(|err| {
+            eprintln!("{} {}", "Error".red(), err);
+            std::process::exit(1);
+        });
+        let data = fs::read(&item.eml).unwrap();
+        println!("文件: {} ({})", item.eml.display(), format_size(data.len() as u64));
+        println!("主题: {}", item.meta.subject);
+        println!("发件人: {}", item.meta.from);
+        println!("收件人: {}", item.meta.to.join(", "));
+        if let Some(message_id) = &item.meta.message_id {
+            println!("Message-ID: {}", message_id);
+        }
+        println!("放入时间: {}", item.meta.created_at);
+        println!("已尝试: {} 次", item.meta.attempts);
+        if let Some(last_attempt) = &item.meta.last_attempt {
+            println!("最后一次尝试: {}", last_attempt);
+        }
+        if let Some(last_error) = &item.meta.last_error {
+            println!("{} {}", "最后的错误:".yellow(), last_error);
+        }
+        println!("邮件头:");
+        for line in String::from_utf8_lossy(&data).lines() {
+            if line.is_empty() {
+                break;
+            }
+            println!("  {}", line);
+        }
+    }
+
+    /// 依次连接服务器、协商加密并认证，打印每一步的耗时和服务器功能，不发送邮件
+    fn check(&self, field_map: &toml::Table) {
+        let settings = self.build_conntent_with_lettre(field_map);
+        println!(
+            "smtp 服务器: {}:{} (security = {})",
+            settings.server, settings.port, settings.security
+        );
+        let started = Instant::now();
+        let step = match settings.security {
+            Security::Tls => "连接并完成 TLS 握手",
+            _ => "连接",
+        };
+        let mut connection = settings
+            .open()
+            .unwrap_or_else(|err| Mail::check_failed(step, err.as_ref(), &settings));
+        Mail::print_step(step, started);
+        println!("  服务器: {}", connection.server_info().name());
+        println!("  功能: {}", smtp::capabilities(&connection).join(", "));
+        match settings.security {
+            Security::StartTls => {
+                let started = Instant::now();
+                settings
+                    .starttls(&mut connection)
+                    .unwrap_or_else(|err| Mail::check_failed("STARTTLS", err.as_ref(), &settings));
+                Mail::print_step("STARTTLS", started);
+                // 部分服务器只在加密之后声明认证方式
+                println!("  功能: {}", smtp::capabilities(&connection).join(", "));
+            }
+            Security::None => println!(
+                "{} 连接没有加密，密码和邮件会以明文传输",
+                "Warning".yellow()
+            ),
+            Security::Tls => {}
+        }
+        let started = Instant::now();
+        match settings.authenticate(&mut connection) {
+            Ok(Some(mechanism)) => Mail::print_step(&format!("认证 ({})", mechanism), started),
+            Ok(None) => println!("{} 没有填写密码，跳过认证", "Warning".yellow()),
+            Err(err) => Mail::check_failed("认证", err.as_ref(), &settings),
+        }
+        let _ = connection.quit();
+        println!("{}", "smtp 配置正确，没有发送邮件".green());
+    }
+
+    fn print_step(step: &str, started: Instant) {
+        println!(
+            "{} {} {}ms",
+            "✓".green(),
+            step,
+            started.elapsed().as_millis()
+        );
+    }
+
+    fn check_failed(step: &str, err: &(dyn Error + 'static), settings: &SmtpSettings) -> ! {
+        eprintln!("{} {} 失败: {}", "✗".red(), step, err);
+        if let Some(hint) = smtp::hint(err, settings) {
+            eprintln!("  {} {}", "提示:".yellow(), hint);
+        }
+        std::process::exit(1);
+    }
+
     fn output_to_file(&self, output: &str, message_data: &[u8]) {
         let mut path = Path::new(output).to_owned();
         // 如果是相对路径 则拼接上当前路径
@@ -113,98 +657,727 @@ impl Mail {
         file.write_all(message_data).unwrap();
     }
 
-    fn build_conntent_with_lettre(&self, field_map: &toml::Table) -> SmtpTransport {
-        let user_email_address = field_map.get("email").unwrap().as_str().unwrap();
-        let user_password = field_map.get("password").unwrap().as_str().unwrap();
-        let smtp_server = field_map.get("smtp_server").unwrap().as_str().unwrap();
-        let smtp_port = field_map.get("smtp_port").unwrap().as_integer().unwrap() as u16;
-
-        let creds = Credentials::new(user_email_address.into(), user_password.into());
-        let mailer = SmtpTransport::relay(smtp_server)
-            .unwrap()
-            .port(smtp_port)
-            .credentials(creds)
-            .build();
-        mailer
+    /// 连接 smtp 服务器的配置，加密方式、超时、HELO 名称和认证方式见 [`SmtpSettings::from_table`]
+    fn build_conntent_with_lettre(&self, field_map: &toml::Table) -> SmtpSettings {
+        match SmtpSettings::from_table(field_map) {
+            Ok(settings) => settings,
+            Err(err) => {
+                eprintln!("{} {}", "Error [mail] smtp 配置错误:".red(), err);
+                std::process::exit(1);
+            }
+        }
     }
 
     fn build_message_with_lettre(
         &self,
         field_map: &toml::Table,
         config_obj: &toml::Table,
-    ) -> Message {
-        let email_address = field_map.get("email").unwrap().as_str().unwrap();
-        let receiver_address = field_map.get("receiver").unwrap().as_str().unwrap();
-        // 判断是否含有自动打包的选项
-        match field_map.get("auto") {
-            Some(auto) => {
-                if auto.as_bool().unwrap() {
-                    let file_name_str = get_default_zip_file_name(config_obj);
-                    let ignore_dir = config_obj.get("zip").unwrap().as_table().unwrap().clone();
-                    let ignore_dir = ignore_dir
-                        .get("ignore")
-                        .unwrap()
-                        .as_array()
-                        .unwrap()
-                        .clone();
-                    let mut ignore_dir = ignore_dir
-                        .iter()
-                        .map(|v| v.as_str().unwrap().to_string())
-                        .collect::<Vec<String>>();
+    ) -> (Message, HistoryEntry) {
+        // 先检查所有邮箱地址 避免打包之后才发现地址错误
+        let from = Mail::addresses(field_map, "email").pop().unwrap_or_else(|| {
+            eprintln!("{}", "Error 没有填写发送邮件的邮箱地址 [mail].email".red());
+            std::process::exit(1);
+        });
+        let recipients = Mail::recipients(field_map);
+        // 非课程文件夹的压缩包放在临时文件夹中 邮件生成后删除
+        let temp = TempDir::new("mail").unwrap();
+        let attachments = self.attachments(field_map, config_obj, &temp);
+        Mail::check_attachment_size(field_map, &attachments);
+        let user_name = field_map.get("user_name").unwrap().as_str().unwrap();
+        let class_name = field_map.get("class_name").unwrap().as_str().unwrap();
+        let time_str = chrono::Local::now().format("%Y%m%d").to_string();
+        let mut subject = format!("{}_{}_{}", class_name, user_name, time_str);
+        let mut history = Mail::history_entry(&attachments, Mail::course(&attachments, config_obj));
+        // 同一课程之前提交过时为重交 主题添加标记 并且回复之前的邮件 方便老师的邮件客户端归为一组
+        let previous = Mail::previous_submissions(field_map, &history);
+        let version = Mail::submission_version(&previous, &history);
+        if version > 1 {
+            if let Some(tag) = Mail::resubmit_tag(field_map, version) {
+                subject = format!("{} {}", tag, subject);
+            }
+        }
+        let attachment_names = attachments
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+
+        // 本地时间
+        let systime = Local::now();
+        let time_str = systime.format("%Y-%m-%d %H:%M:%S").to_string();
+        // 配置了正文模板时同时发送纯文本和渲染后的 HTML 没有配置时正文为发送时间
+        let body = self.body(field_map, config_obj, &attachment_names.join(", "), &time_str);
+        let mut content = match body {
+            Some(markdown) => MultiPart::mixed().multipart(MultiPart::alternative_plain_html(
+                markdown.clone(),
+                markdown::to_html(&markdown),
+            )),
+            None => MultiPart::mixed().singlepart(
+                SinglePart::builder()
+                    .content_type(header::ContentType::TEXT_PLAIN)
+                    .body(Body::new(time_str)),
+            ),
+        };
+        for path in &attachments {
+            content = content.singlepart(Mail::attachment_part(path));
+        }
+        let mut builder = Mail::message_builder(from, subject, recipients);
+        // 跳过没有 Message-ID 的记录 避免生成空的 In-Reply-To 和 References
+        let previous_ids = previous
+            .iter()
+            .map(|entry| entry.message_id.trim())
+            .filter(|message_id| !message_id.is_empty())
+            .collect::<Vec<&str>>();
+        if let Some(last) = previous_ids.last() {
+            builder = builder
+                .in_reply_to(last.to_string())
+                .references(previous_ids.join(" "));
+        }
+        let mut message = builder.multipart(content).unwrap();
+        message.headers_mut().set(Date::now());
+        history.message_id = message
+            .headers()
+            .get_raw("Message-ID")
+            .unwrap_or_default()
+            .to_string();
+        history.from = message
+            .envelope()
+            .from()
+            .map(|from| from.to_string())
+            .unwrap_or_default();
+        history.recipients = message.envelope().to().iter().map(|to| to.to_string()).collect();
+        history.subject = message.headers().get_raw("Subject").unwrap_or_default().to_string();
+        (message, history)
+    }
+
+    /// 邮件头中的发件人、主题和收件人
+    /// 保留 Bcc 邮件头，`-o` 输出的文件可以用 send-eml 发送给所有收件人，发送前再删除 Bcc
+    fn message_builder(from: Mailbox, subject: String, recipients: Recipients) -> MessageBuilder {
+        let mut builder = Message::builder()
+            .from(from)
+            .subject(subject)
+            .message_id(None)
+            .keep_bcc();
+        for mailbox in recipients.to {
+            builder = builder.to(mailbox);
+        }
+        for mailbox in recipients.cc {
+            builder = builder.cc(mailbox);
+        }
+        for mailbox in recipients.bcc {
+            builder = builder.bcc(mailbox);
+        }
+        for mailbox in recipients.reply_to {
+            builder = builder.reply_to(mailbox);
+        }
+        builder
+    }
 
-                    Zip::_zip(".", &mut ignore_dir, &file_name_str, config_obj);
+    /// 发送成功后写入提交历史的记录，先记录附件和课程
+    /// Message-ID、收件人和主题在邮件生成后填写，发送时间在发送成功时填写
+    fn history_entry(attachments: &[PathBuf], course: Option<(i64, Option<String>)>) -> HistoryEntry {
+        let attachment_infos = attachments
+            .iter()
+            .map(|path| HistoryAttachment {
+                name: path.file_name().unwrap().to_string_lossy().to_string(),
+                size: fs::metadata(path).unwrap().len(),
+                sha256: sha256_file(path).unwrap(),
+            })
+            .collect();
+        let (courses_number, course_name) = match course {
+            Some((number, name)) => (Some(number), name),
+            None => (None, None),
+        };
+        HistoryEntry {
+            courses_number,
+            course_name,
+            attachments: attachment_infos,
+            ..Default::default()
+        }
+    }
+
+    /// 与提交历史比较，返回同一课程之前的提交
+    /// 附件与之前的某次提交完全相同时提示，发送时需要确认(`--force` 时直接发送)
+    fn previous_submissions(field_map: &toml::Table, entry: &HistoryEntry) -> Vec<HistoryEntry> {
+        let history = Mail::history_file(field_map);
+        let entries = match history.entries() {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!(
+                    "{} {}: {}",
+                    "Warning 读取提交历史失败，不检查重复提交".yellow(),
+                    history.path.display(),
+                    err
+                );
+                return Vec::new();
+            }
+        };
+        if let Some(duplicate) = Mail::duplicate_submission(&entries, entry) {
+            println!(
+                "{} 附件与 {} 发送的提交完全相同 ({})",
+                "Warning".yellow(),
+                duplicate
+                    .sent_time()
+                    .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| duplicate.sent_at.clone()),
+                duplicate.message_id
+            );
+            let get_bool = |key: &str| field_map.get(key).is_some_and(|v| v.as_bool().unwrap());
+            if get_bool("send") && !get_bool("force") {
+                print!("仍然发送? [y/N] ");
+                std::io::stdout().flush().unwrap();
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer).unwrap();
+                if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                    eprintln!("{}", "已取消发送".red());
+                    std::process::exit(1);
                 }
             }
-            None => {}
         }
-        let user_name = field_map.get("user_name").unwrap().as_str().unwrap();
-        let class_name = field_map.get("class_name").unwrap().as_str().unwrap();
-        let time_str = chrono::Local::now().format("%Y%m%d").to_string();
-        // match附件路径是否存在
-        let attachment_path_str = match field_map.get("attachment") {
-            Some(attachment) => attachment.as_str().unwrap().to_string(),
-            None => {
-                // 用户没有输入附件路径 使用默认的附件路径
-                format!("{}_{}_{}.zip", class_name, user_name, time_str)
-            }
-        };
-        // 当前命令行所在路径
-        let current_path = std::env::current_dir().unwrap();
-        let attachment_path = current_path.join(attachment_path_str);
-        let attachment_name = attachment_path.file_name().unwrap().to_str().unwrap(); // 附件名称
-        // 附件名称需要经过base64，在拼接=?utf-8?B? 尾部拼接?=
-        let mut attachment_name_base64 =  base64::prelude::BASE64_STANDARD.encode(attachment_name.as_bytes());
-        attachment_name_base64 = format!("=?utf-8?B?{}?=", attachment_name_base64);
-        let subject = format!("{}_{}_{}", class_name, user_name, time_str);
-        // 判断附件路径是否存在
-        if !attachment_path.exists() {
-            eprintln!("{} {:?}", "Error 附件路径不存在:".red(), attachment_path);
+        match entry.courses_number {
+            Some(number) => entries
+                .into_iter()
+                .filter(|previous| previous.courses_number == Some(number))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// 同一课程最近一次附件完全相同的提交，不知道课程时无法判断是不是同一次作业 不检查重复
+    fn duplicate_submission<'a>(
+        entries: &'a [HistoryEntry],
+        entry: &HistoryEntry,
+    ) -> Option<&'a HistoryEntry> {
+        entry.courses_number?;
+        entries.iter().rev().find(|previous| {
+            previous.courses_number == entry.courses_number
+                && previous.same_attachments(&entry.attachments)
+        })
+    }
+
+    /// 这次是同一课程的第几次提交，附件完全相同的提交只算一次
+    fn submission_version(previous: &[HistoryEntry], entry: &HistoryEntry) -> usize {
+        let mut versions: Vec<&HistoryEntry> = Vec::new();
+        for previous in previous {
+            if !previous.same_attachments(&entry.attachments)
+                && !versions
+                    .iter()
+                    .any(|version| version.same_attachments(&previous.attachments))
+            {
+                versions.push(previous);
+            }
+        }
+        versions.len() + 1
+    }
+
+    /// 重交时邮件主题的前缀 `[mail].resubmit_tag`，`{version}` 为第几次提交，为空时不添加
+    fn resubmit_tag(field_map: &toml::Table, version: usize) -> Option<String> {
+        let tag = field_map
+            .get("resubmit_tag")
+            .map(|v| v.as_str().unwrap())
+            .unwrap_or(DEFAULT_RESUBMIT_TAG);
+        if tag.is_empty() {
+            return None;
+        }
+        Some(tag.replace("{version}", &version.to_string()))
+    }
+
+    /// 提交的课程，优先使用附件压缩包清单中的课程，其次是当前所在的课程文件夹
+    fn course(attachments: &[PathBuf], config_obj: &toml::Table) -> Option<(i64, Option<String>)> {
+        Mail::course_from_manifest(attachments, config_obj).or_else(|| {
+            let dir = std::env::current_dir().ok()?.canonicalize().ok()?;
+            let (number, name) = find_project(&dir)?;
+            Some((number, Some(name)))
+        })
+    }
+
+    /// 附件压缩包清单中记录的课程
+    fn course_from_manifest(
+        attachments: &[PathBuf],
+        config_obj: &toml::Table,
+    ) -> Option<(i64, Option<String>)> {
+        let zip_filed_map = zip::config_filed_map(config_obj);
+        let password = zip_filed_map
+            .get("password")
+            .map(|v| v.as_str().unwrap())
+            .unwrap_or_default();
+        attachments
+            .iter()
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("zip") || ext.eq_ignore_ascii_case("7z"))
+            })
+            .find_map(|path| {
+                let (_, manifest) = read_manifest_file(path, password).ok()??;
+                Some((manifest.courses_number?, manifest.course_name))
+            })
+    }
+
+    /// 邮件的附件，`-a` 时先压缩当前文件夹
+    /// `-f` 可以重复使用并支持通配符，文件夹会先压缩再作为附件，每个文件夹一个压缩包
+    /// 都没有指定时使用今天默认名称的压缩包
+    fn attachments(
+        &self,
+        field_map: &toml::Table,
+        config_obj: &toml::Table,
+        temp: &TempDir,
+    ) -> Vec<PathBuf> {
+        let zip_filed_map = zip::config_filed_map(config_obj);
+        // 生成的压缩包 -> 压缩的文件夹，不同的文件夹压缩到同一路径时后压缩的会覆盖之前的，需要报错
+        let mut archives = BTreeMap::<PathBuf, PathBuf>::new();
+        let mut zip_dir = |dir_path: &Path, output: Option<PathBuf>| {
+            let mut zip_filed_map = zip_filed_map.clone();
+            zip_filed_map.insert(
+                "dir_path".to_string(),
+                toml::Value::from(dir_path.to_string_lossy().as_ref()),
+            );
+            let source = dir_path.canonicalize().unwrap();
+            let output =
+                output.unwrap_or_else(|| Zip::output_path(&zip_filed_map, config_obj));
+            match archives.get(&output) {
+                Some(previous) if *previous == source => return None,
+                Some(previous) => {
+                    eprintln!(
+                        "{} {}\n  {}\n  {}",
+                        "Error 两个文件夹的压缩包路径相同:".red(),
+                        output.display(),
+                        previous.display(),
+                        source.display()
+                    );
+                    std::process::exit(1);
+                }
+                None => archives.insert(output.clone(), source),
+            };
+            zip_filed_map.insert(
+                "output".to_string(),
+                toml::Value::from(output.to_string_lossy().as_ref()),
+            );
+            // 自动打包时默认校验压缩包 除非配置文件中显式关闭
+            zip_filed_map
+                .entry("verify")
+                .or_insert(toml::Value::Boolean(true));
+            Zip::_zip(&zip_filed_map, config_obj)
+        };
+        let mut attachments = Vec::new();
+        if let Some(true) = field_map.get("auto").map(|auto| auto.as_bool().unwrap()) {
+            attachments.extend(zip_dir(Path::new("."), None));
+        }
+        let current_dir = std::env::current_dir().unwrap();
+        for attachment in Mail::string_list(field_map, "attachment") {
+            let paths = Mail::expand_attachment(&current_dir, &attachment).unwrap_or_else(|err| {
+                eprintln!("{} {}", "Error".red(), err);
+                std::process::exit(1);
+            });
+            for path in paths {
+                if path.is_dir() {
+                    let output = Mail::dir_archive_path(&zip_filed_map, config_obj, &path, temp);
+                    attachments.extend(zip_dir(&path, Some(output)));
+                } else {
+                    attachments.push(path);
+                }
+            }
+        }
+        if attachments.is_empty() {
+            // 用户没有输入附件路径 使用默认的附件路径
+            attachments.push(Zip::output_path(&zip_filed_map, config_obj));
+        }
+        Mail::unique_attachments(attachments).unwrap_or_else(|err| {
+            eprintln!("{} {}", "Error".red(), err);
             std::process::exit(1);
+        })
+    }
+
+    /// 展开 `-f` 中的通配符，相对路径相对于 `current_dir`
+    fn expand_attachment(current_dir: &Path, attachment: &str) -> Result<Vec<PathBuf>, String> {
+        let path = current_dir.join(attachment);
+        // 文件名本身可能包含通配符 例如 实验[1].pdf 存在时按原样使用
+        if !attachment.contains(['*', '?', '[']) || path.exists() {
+            return Ok(vec![path]);
+        }
+        // 只有附件路径中的通配符生效 当前路径中的 [ ] 等字符需要转义
+        let pattern =
+            Path::new(&glob::Pattern::escape(&current_dir.to_string_lossy())).join(attachment);
+        let paths = glob::glob(&pattern.to_string_lossy())
+            .map_err(|err| format!("无效的匹配规则: {} {}", attachment, err))?
+            .filter_map(|path| path.ok())
+            .collect::<Vec<PathBuf>>();
+        if paths.is_empty() {
+            return Err(format!("没有匹配的附件: {}", attachment));
+        }
+        Ok(paths)
+    }
+
+    /// 去掉重复的附件，附件必须存在并且文件名不能相同
+    fn unique_attachments(attachments: Vec<PathBuf>) -> Result<Vec<PathBuf>, String> {
+        let mut names = BTreeSet::new();
+        let mut unique = Vec::new();
+        for path in attachments {
+            if unique.contains(&path) {
+                continue;
+            }
+            if !path.exists() {
+                return Err(format!("附件路径不存在: {:?}", path));
+            }
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            if !names.insert(name.clone()) {
+                return Err(format!("附件重名: {}", name));
+            }
+            unique.push(path);
+        }
+        Ok(unique)
+    }
+
+    /// `-f` 指定的文件夹压缩后的路径
+    /// 课程文件夹按 `course_name_template` 命名放在默认的输出位置，其他文件夹按文件夹名称命名放在临时文件夹中
+    fn dir_archive_path(
+        zip_filed_map: &toml::Table,
+        config_obj: &toml::Table,
+        dir: &Path,
+        temp: &TempDir,
+    ) -> PathBuf {
+        let extension = Zip::encryption(zip_filed_map).extension();
+        let dir = dir.canonicalize().unwrap();
+        let dir_name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "attachment".to_string());
+        match parse_project_name(&dir_name) {
+            Some((number, name)) => {
+                let template = get_name_template(
+                    config_obj,
+                    "course_name_template",
+                    DEFAULT_COURSE_NAME_TEMPLATE,
+                );
+                let today = chrono::Local::now().date_naive();
+                let name = render_zip_file_name(
+                    config_obj,
+                    &template,
+                    today,
+                    Some((&number.to_string(), &name)),
+                );
+                Zip::output_path(zip_filed_map, config_obj)
+                    .with_file_name(format!("{}.{}", name, extension))
+            }
+            None => temp.0.join(format!("{}.{}", dir_name, extension)),
+        }
+    }
+
+    /// 附件总大小超过 `[mail].max_attachment_size`(默认 50MB) 时不生成邮件
+    fn check_attachment_size(field_map: &toml::Table, attachments: &[PathBuf]) {
+        let limit = match field_map.get("max_attachment_size") {
+            Some(value) => parse_size(value).unwrap_or_else(|| {
+                eprintln!("{} {}", "Error 无效的 max_attachment_size:".red(), value);
+                std::process::exit(1);
+            }),
+            None => DEFAULT_MAX_ATTACHMENT_SIZE,
+        };
+        let sizes = attachments
+            .iter()
+            .map(|path| (path, path.metadata().unwrap().len()))
+            .collect::<Vec<(&PathBuf, u64)>>();
+        let total = sizes.iter().map(|(_, size)| size).sum::<u64>();
+        if total <= limit {
+            return;
+        }
+        eprintln!(
+            "{} {} 超过限制 {}",
+            "Error 附件总大小".red(),
+            format_size(total),
+            format_size(limit)
+        );
+        for (path, size) in sizes {
+            eprintln!("  {} ({})", path.display(), format_size(size));
         }
+        std::process::exit(1);
+    }
+
+    /// 附件的 MIME 类型根据扩展名判断，未知的扩展名使用 application/octet-stream
+    /// 文件名使用 RFC 2231 编码，同时带有 RFC 2047 编码的名称给旧的客户端使用
+    fn attachment_part(path: &Path) -> SinglePart {
+        let attachment_name = path.file_name().unwrap().to_string_lossy(); // 附件名称
+        let content_type = mime_guess::from_path(path).first_or_octet_stream();
         // 读取附件内容
-        
-        let file_data = fs::read(&attachment_path).unwrap();
-        let attachment_singpart: SinglePart = SinglePart::builder()
-            .header(header::ContentType::parse(mime::APPLICATION_OCTET_STREAM.essence_str()).unwrap())
-            .header(lettre::message::header::ContentDisposition::attachment(
-                &attachment_name_base64,
+        let file_data = fs::read(path).unwrap();
+        SinglePart::builder()
+            .header(AttachmentContentType::new(
+                content_type.essence_str(),
+                &attachment_name,
             ))
-            .body(file_data);
+            .header(AttachmentDisposition::new(&attachment_name))
+            .body(file_data)
+    }
 
-        // 本地时间
-        let systime = Local::now();
-        let body = Body::new(format!("{}", systime.format("%Y-%m-%d %H:%M:%S")));
-        let mut message = Message::builder()
-            .from(email_address.parse().unwrap())
-            .to(receiver_address.parse().unwrap())
-            .subject(subject)
-            .multipart(
-                MultiPart::mixed().singlepart(
-                    SinglePart::builder().content_type(header::ContentType::TEXT_PLAIN).body(body),
-                ).singlepart(attachment_singpart)
-            )
-            .unwrap();
-        message.headers_mut().set(Date::now());
-        message
+    /// 配置中的一个字符串或者字符串列表
+    fn string_list(field_map: &toml::Table, key: &str) -> Vec<String> {
+        match field_map.get(key) {
+            None => Vec::new(),
+            Some(toml::Value::Array(values)) => values
+                .iter()
+                .map(|value| value.as_str().unwrap().to_string())
+                .collect(),
+            Some(value) => vec![value.as_str().unwrap().to_string()],
+        }
+    }
+
+    /// 读取并校验配置中的邮箱地址，值可以是一个地址或者地址列表，空字符串会被忽略
+    /// 地址可以带名称，例如 `张老师 <teacher@qq.com>`
+    fn addresses(field_map: &toml::Table, key: &str) -> Vec<Mailbox> {
+        Mail::parse_addresses(field_map, key).unwrap_or_else(|err| {
+            eprintln!("{} {}", "Error 配置错误:".red(), err);
+            std::process::exit(1);
+        })
+    }
+
+    fn parse_addresses(field_map: &toml::Table, key: &str) -> Result<Vec<Mailbox>, String> {
+        let values = match field_map.get(key) {
+            None => Vec::new(),
+            Some(toml::Value::Array(values)) => values.iter().collect(),
+            Some(value) => vec![value],
+        };
+        let mut addresses = Vec::new();
+        for value in values {
+            let Some(address) = value.as_str() else {
+                return Err(format!("[mail].{} 必须是字符串或字符串列表", key));
+            };
+            if address.trim().is_empty() {
+                continue;
+            }
+            match address.trim().parse::<Mailbox>() {
+                Ok(mailbox) => addresses.push(mailbox),
+                Err(err) => {
+                    return Err(format!(
+                        "[mail].{} 中的无效邮箱地址 {}: {}",
+                        key, address, err
+                    ))
+                }
+            }
+        }
+        Ok(addresses)
+    }
+
+    /// `to` `cc` `bcc` `reply_to` 中的地址，没有 `to` 时使用旧版的 `receiver`
+    fn recipients(field_map: &toml::Table) -> Recipients {
+        let mut to = Mail::addresses(field_map, "to");
+        if to.is_empty() {
+            to = Mail::addresses(field_map, "receiver");
+        }
+        if to.is_empty() {
+            eprintln!(
+                "{}",
+                "Error 没有收件人，请在 [mail].to 中填写接收邮件的邮箱地址".red()
+            );
+            std::process::exit(1);
+        }
+        Recipients {
+            to,
+            cc: Mail::addresses(field_map, "cc"),
+            bcc: Mail::addresses(field_map, "bcc"),
+            reply_to: Mail::addresses(field_map, "reply_to"),
+        }
+    }
+
+    /// 邮件正文的 Markdown，优先使用 `--message` 和 `--body-file`，其次是 `[mail].body_template_file` 和 `body_template`
+    /// 都没有时返回 `None`
+    /// 可以使用压缩包名称模板中的占位符，以及 `{attachment}` 附件名称、`{time}` 发送时间
+    fn body(
+        &self,
+        field_map: &toml::Table,
+        config_obj: &toml::Table,
+        attachment_name: &str,
+        time_str: &str,
+    ) -> Option<String> {
+        let read_body_file = |path: &str| match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("{} {}: {}", "Error 读取邮件正文失败:".red(), path, err);
+                std::process::exit(1);
+            }
+        };
+        let template = if let Some(message) = field_map.get("message") {
+            message.as_str().unwrap().to_string()
+        } else if let Some(body_file) = field_map.get("body_file") {
+            read_body_file(body_file.as_str().unwrap())
+        } else if let Some(template_file) = field_map.get("body_template_file") {
+            // 相对路径相对于配置文件所在的文件夹 文件不存在时报错 不会把路径当作正文发送
+            let path = config_dir().join(template_file.as_str().unwrap());
+            read_body_file(&path.to_string_lossy())
+        } else {
+            field_map.get("body_template")?.as_str().unwrap().to_string()
+        };
+        let project = std::env::current_dir()
+            .ok()
+            .and_then(|dir| find_project(&dir.canonicalize().ok()?));
+        let number = project.as_ref().map(|(number, _)| number.to_string());
+        let project = project
+            .as_ref()
+            .map(|(_, name)| (number.as_deref().unwrap(), name.as_str()));
+        let today = Local::now().date_naive();
+        let body = render_zip_file_name(config_obj, &template, today, project)
+            .replace("{attachment}", attachment_name)
+            .replace("{time}", time_str);
+        Some(body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn output_keeps_bcc_for_send_eml() {
+        let field_map = r#"
+            to = "张老师 <teacher@example.com>"
+            bcc = ["me@example.com"]
+        "#
+        .parse::<toml::Table>()
+        .unwrap();
+        let message = Mail::message_builder(
+            "me@example.com".parse().unwrap(),
+            "CR49_张三_20240520".to_string(),
+            Mail::recipients(&field_map),
+        )
+        .body("正文".to_string())
+        .unwrap();
+        let temp = TempDir::new("mail-bcc").unwrap();
+        let output = temp.0.join("mail.eml");
+        let mail = Mail::parse_from(["mail"]);
+        mail.output_to_file(output.to_str().unwrap(), &message.formatted());
+
+        // send-eml 读取 -o 输出的文件时 信封中包含密送的收件人
+        let data = fs::read(&output).unwrap();
+        let eml = Eml::parse(&data).unwrap();
+        assert_eq!(
+            Mail::eml_recipients(&eml, None),
+            vec!["teacher@example.com", "me@example.com"]
+        );
+        // 发送的邮件中删除了 Bcc 其余邮件头不变
+        let sent = Eml::parse(&remove_header(&data, "Bcc")).unwrap();
+        assert!(sent.header("Bcc").is_none());
+        assert_eq!(sent.header("To"), eml.header("To"));
+        assert_eq!(sent.headers.len(), eml.headers.len() - 1);
+    }
+
+    #[test]
+    fn parse_addresses_and_receiver() {
+        let table = |content: &str| content.parse::<toml::Table>().unwrap();
+        let emails = |mailboxes: &[Mailbox]| {
+            mailboxes
+                .iter()
+                .map(|mailbox| mailbox.email.to_string())
+                .collect::<Vec<String>>()
+        };
+        // 没有 to 时使用旧版的 receiver
+        let recipients = Mail::recipients(&table(r#"receiver = "teacher@example.com""#));
+        assert_eq!(emails(&recipients.to), vec!["teacher@example.com"]);
+        let recipients = Mail::recipients(&table(
+            r#"
+            to = ["a@example.com", "b@example.com"]
+            receiver = "teacher@example.com"
+            "#,
+        ));
+        assert_eq!(
+            emails(&recipients.to),
+            vec!["a@example.com", "b@example.com"]
+        );
+
+        let field_map = table(
+            r#"
+            cc = ["张老师 <ta@example.com>", ""]
+            bcc = "not an address"
+            reply_to = 1
+            "#,
+        );
+        let cc = Mail::parse_addresses(&field_map, "cc").unwrap();
+        assert_eq!(emails(&cc), vec!["ta@example.com"]);
+        assert_eq!(cc[0].name.as_deref(), Some("张老师"));
+        assert!(Mail::parse_addresses(&field_map, "to").unwrap().is_empty());
+        let err = Mail::parse_addresses(&field_map, "bcc").unwrap_err();
+        assert!(err.starts_with("[mail].bcc 中的无效邮箱地址 not an address"));
+        let err = Mail::parse_addresses(&field_map, "reply_to").unwrap_err();
+        assert_eq!(err, "[mail].reply_to 必须是字符串或字符串列表");
+    }
+
+    #[test]
+    fn expand_and_dedupe_attachments() {
+        let temp = TempDir::new("mail-attachments").unwrap();
+        for name in [
+            "imgs/a.png",
+            "imgs/b.png",
+            "imgs/c.txt",
+            "实验[1].pdf",
+            "other/a.png",
+        ] {
+            let path = temp.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, name).unwrap();
+        }
+        let mut pngs = Mail::expand_attachment(&temp.0, "imgs/*.png").unwrap();
+        pngs.sort();
+        assert_eq!(
+            pngs,
+            vec![temp.0.join("imgs/a.png"), temp.0.join("imgs/b.png")]
+        );
+        // 存在的文件名按原样使用 不作为通配符
+        assert_eq!(
+            Mail::expand_attachment(&temp.0, "实验[1].pdf").unwrap(),
+            vec![temp.0.join("实验[1].pdf")]
+        );
+        assert_eq!(
+            Mail::expand_attachment(&temp.0, "*.doc").unwrap_err(),
+            "没有匹配的附件: *.doc"
+        );
+
+        // 同一个附件只保留一个
+        let mut attachments = pngs.clone();
+        attachments.extend(Mail::expand_attachment(&temp.0, "imgs/a.png").unwrap());
+        assert_eq!(Mail::unique_attachments(attachments).unwrap(), pngs);
+        // 不同文件夹中的同名附件
+        let mut attachments = pngs.clone();
+        attachments.push(temp.0.join("other/a.png"));
+        assert_eq!(
+            Mail::unique_attachments(attachments).unwrap_err(),
+            "附件重名: a.png"
+        );
+        assert!(Mail::unique_attachments(vec![temp.0.join("missing.zip")]).is_err());
+    }
+
+    #[test]
+    fn duplicate_and_version() {
+        let submission = |message_id: &str, course: Option<i64>, sha256: &str| HistoryEntry {
+            message_id: message_id.to_string(),
+            courses_number: course,
+            attachments: vec![HistoryAttachment {
+                name: "CR49_张三_20240520.zip".to_string(),
+                size: 1024,
+                sha256: sha256.repeat(32),
+            }],
+            ..Default::default()
+        };
+        let entries = vec![
+            submission("<1@host>", None, "00"),
+            submission("<2@host>", Some(13), "11"),
+            submission("<3@host>", Some(13), "11"),
+            submission("<4@host>", Some(13), "22"),
+        ];
+
+        // 没有课程时不与其他没有课程的提交比较
+        assert!(Mail::duplicate_submission(&entries, &submission("", None, "00")).is_none());
+        let duplicate = Mail::duplicate_submission(&entries, &submission("", Some(13), "11"));
+        assert_eq!(duplicate.unwrap().message_id, "<3@host>");
+        assert!(Mail::duplicate_submission(&entries, &submission("", Some(14), "11")).is_none());
+
+        // 附件完全相同的提交只算一次
+        let previous = &entries[1..];
+        assert_eq!(
+            Mail::submission_version(previous, &submission("", Some(13), "33")),
+            3
+        );
+        assert_eq!(
+            Mail::submission_version(previous, &submission("", Some(13), "22")),
+            2
+        );
+        assert_eq!(
+            Mail::submission_version(&[], &submission("", Some(13), "22")),
+            1
+        );
     }
 }
@@ -3,6 +3,10 @@ use toml::Table;
 pub mod new;
 pub mod zip;
 pub mod mail;
+pub mod unzip;
+pub mod verify;
+pub mod history;
+pub mod status;
 const GLOBAL_TABLE_NAME: &str = "global";
 
 pub fn get_global_filed_map(config_obj: &toml::Table) -> Table {
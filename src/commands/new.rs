@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
 use colored::Colorize;
@@ -184,3 +184,37 @@ impl New {
         format!("{}-{}", courses_number, course_name)
     }
 }
+
+/// 从项目文件夹名称 `课程序号-课程名称` 中解析出课程序号和课程名称
+pub fn parse_project_name(name: &str) -> Option<(i64, String)> {
+    let (courses_number, course_name) = name.split_once('-')?;
+    let courses_number = courses_number.parse().ok()?;
+    if course_name.is_empty() {
+        return None;
+    }
+    Some((courses_number, course_name.to_string()))
+}
+
+/// 查找路径本身或者它的上级中的项目文件夹，返回课程序号和课程名称
+pub fn find_project(path: &Path) -> Option<(i64, String)> {
+    path.ancestors()
+        .filter_map(|dir| dir.file_name()?.to_str())
+        .find_map(parse_project_name)
+}
+
+/// 工作目录中的全部项目文件夹，按课程序号排序，返回课程序号、课程名称和文件夹路径
+pub fn list_projects(workspace: &Path) -> Vec<(i64, String, PathBuf)> {
+    let Ok(read_dir) = std::fs::read_dir(workspace) else {
+        return Vec::new();
+    };
+    let mut projects = read_dir
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let (number, name) = parse_project_name(entry.file_name().to_str()?)?;
+            Some((number, name, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    projects.sort();
+    projects
+}
@@ -0,0 +1,208 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use clap::Parser;
+use colored::Colorize;
+use toml::Table;
+
+use crate::tools::history::HistoryEntry;
+
+use super::mail::Mail;
+use super::new::list_projects;
+use super::MyCommand;
+
+/// 历史记录文件的位置与 mail 命令共用 `[mail]` 中的配置
+const TABLE_NAME: &str = "mail";
+
+#[derive(Parser, Debug)]
+/// 查看工作目录中每个课程的提交状态
+/// 根据提交历史列出最近一次提交的时间和附件，以及提交之后是否修改过
+pub struct Status {}
+
+impl MyCommand for &Status {
+    fn run(&self, config_obj: &toml::Table) {
+        let filed_map = self.parse_field(config_obj);
+        self.status(&filed_map, config_obj);
+    }
+    fn get_global_filed_map(&self, config_obj: &Table) -> Table {
+        super::get_global_filed_map(config_obj)
+    }
+
+    fn get_filed_map(&self, config_obj: &Table) -> Table {
+        match config_obj.get(TABLE_NAME) {
+            Some(table) => {
+                let table = table.as_table().unwrap();
+                table.clone()
+            }
+            None => Table::new(),
+        }
+    }
+}
+
+impl IntoIterator for &Status {
+    type Item = (String, toml::Value);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+    fn into_iter(self) -> Self::IntoIter {
+        Vec::new().into_iter()
+    }
+}
+
+impl Status {
+    fn status(&self, filed_map: &Table, config_obj: &Table) {
+        let workspace = config_obj
+            .get("new")
+            .and_then(|new| new.as_table()?.get("workspace")?.as_str())
+            .unwrap_or_default();
+        let projects = list_projects(Path::new(workspace));
+        if projects.is_empty() {
+            println!("工作目录中没有课程: {}", workspace);
+            return;
+        }
+        let history = Mail::history_file(filed_map);
+        let entries = history.entries().unwrap_or_else(|err| {
+            eprintln!(
+                "{} {}: {}",
+                "Error 读取提交历史失败".red(),
+                history.path.display(),
+                err
+            );
+            std::process::exit(1);
+        });
+        let statuses = course_statuses(&projects, &entries);
+        let mut unsubmitted = 0;
+        for status in &statuses {
+            let (number, name) = (status.number, &status.name);
+            let Some(last) = status.last else {
+                unsubmitted += 1;
+                println!("{:>4}-{}  {}", number, name, "未提交".yellow());
+                continue;
+            };
+            let time = last
+                .sent_time()
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| last.sent_at.clone());
+            let attachments = last
+                .attachments
+                .iter()
+                .map(|attachment| attachment.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ");
+            println!(
+                "{:>4}-{}  {} {}  {}  (共提交 {} 次){}",
+                number,
+                name,
+                "已提交".green(),
+                time,
+                attachments,
+                status.count,
+                if status.modified {
+                    "  提交后有修改".yellow().to_string()
+                } else {
+                    String::new()
+                }
+            );
+        }
+        println!("共 {} 个课程，{} 个未提交", projects.len(), unsubmitted);
+    }
+}
+
+/// 一个课程的提交状态
+struct CourseStatus<'a> {
+    number: i64,
+    name: String,
+    /// 最近一次提交，未提交时为 `None`
+    last: Option<&'a HistoryEntry>,
+    /// 提交的次数
+    count: usize,
+    /// 最近一次提交之后课程文件夹中是否有修改
+    modified: bool,
+}
+
+/// 根据提交历史得到每个课程的提交状态，`projects` 为 [`list_projects`] 的结果
+fn course_statuses<'a>(
+    projects: &[(i64, String, PathBuf)],
+    entries: &'a [HistoryEntry],
+) -> Vec<CourseStatus<'a>> {
+    projects
+        .iter()
+        .map(|(number, name, path)| {
+            let submissions = entries
+                .iter()
+                .filter(|entry| entry.courses_number == Some(*number))
+                .collect::<Vec<&HistoryEntry>>();
+            let last = submissions.last().copied();
+            let modified = last
+                .and_then(|last| last.sent_time())
+                .is_some_and(|t| modified_after(path, t.into()));
+            CourseStatus {
+                number: *number,
+                name: name.clone(),
+                last,
+                count: submissions.len(),
+                modified,
+            }
+        })
+        .collect()
+}
+
+/// 课程文件夹中是否有文件在指定时间之后修改过，忽略隐藏文件
+fn modified_after(dir: &Path, time: SystemTime) -> bool {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+        })
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .any(|entry| {
+            entry
+                .metadata()
+                .ok()
+                .and_then(|metadata| metadata.modified().ok())
+                .is_some_and(|modified| modified > time)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tools::files::TempDir;
+
+    #[test]
+    fn latest_submission_per_course() {
+        let temp = TempDir::new("status").unwrap();
+        for name in ["13-虚函数/main.cpp", "14-纯虚函数/main.cpp"] {
+            let path = temp.0.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "int main() {}\n").unwrap();
+        }
+        let projects = list_projects(&temp.0);
+        let submission = |message_id: &str, number: i64, sent_at: &str| HistoryEntry {
+            message_id: message_id.to_string(),
+            sent_at: sent_at.to_string(),
+            courses_number: Some(number),
+            ..Default::default()
+        };
+        let entries = vec![
+            submission("<1@host>", 13, "2024-05-20T21:30:00+08:00"),
+            submission("<2@host>", 12, "2024-05-21T21:30:00+08:00"),
+            submission("<3@host>", 13, "2999-01-01T00:00:00+08:00"),
+        ];
+
+        let statuses = course_statuses(&projects, &entries);
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0].number, 13);
+        assert_eq!(statuses[0].last.unwrap().message_id, "<3@host>");
+        assert_eq!(statuses[0].count, 2);
+        // 最近一次提交之后没有修改
+        assert!(!statuses[0].modified);
+        assert_eq!(statuses[1].number, 14);
+        assert!(statuses[1].last.is_none());
+        assert_eq!(statuses[1].count, 0);
+
+        // 提交之后修改过课程文件夹中的文件
+        let statuses = course_statuses(&projects, &entries[..2]);
+        assert_eq!(statuses[0].last.unwrap().message_id, "<1@host>");
+        assert!(statuses[0].modified);
+    }
+}
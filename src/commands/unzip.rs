@@ -0,0 +1,293 @@
+use std::{
+    fs::File,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use clap::Parser;
+use colored::Colorize;
+use toml::Table;
+
+use crate::tools::archive::{extract_files, is_7z, list_extract_entries, needs_password};
+use crate::tools::zip_names::read_central_directory;
+
+use super::new::{list_projects, parse_project_name};
+use super::MyCommand;
+
+const TABLE_NAME: &str = "unzip";
+
+#[derive(Parser, Debug)]
+/// 解压老师发送的资料压缩包
+/// 自动识别 GBK/GB18030 编码的中文文件名，支持 7z 和加密的压缩包
+pub struct Unzip {
+    /// 需要解压的压缩包路径
+    pub archive: String,
+    #[arg(long)]
+    /// 解压到的课程，可以是课程序号(例如: 12)或者文件夹路径，默认为当前所在的课程文件夹
+    pub into: Option<String>,
+    #[arg(long)]
+    /// 从终端输入解压密码(不回显)，压缩包加密时会自动询问
+    pub password_prompt: bool,
+    #[arg(long)]
+    /// 覆盖已经存在的文件，默认跳过
+    pub overwrite: bool,
+    #[arg(long)]
+    /// 只列出压缩包中的文件名以及读取文件名时使用的编码，不解压
+    pub list: bool,
+}
+
+impl MyCommand for &Unzip {
+    fn run(&self, config_obj: &toml::Table) {
+        let filed_map = self.parse_field(config_obj);
+        self.unzip(&filed_map, config_obj);
+    }
+    fn get_global_filed_map(&self, config_obj: &Table) -> Table {
+        super::get_global_filed_map(config_obj)
+    }
+
+    fn get_filed_map(&self, config_obj: &Table) -> Table {
+        match config_obj.get(TABLE_NAME) {
+            Some(table) => {
+                let table = table.as_table().unwrap();
+                table.clone()
+            }
+            None => Table::new(),
+        }
+    }
+}
+
+impl IntoIterator for &Unzip {
+    type Item = (String, toml::Value);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+    fn into_iter(self) -> Self::IntoIter {
+        let mut vec = vec![(
+            "archive".to_string(),
+            toml::Value::String(self.archive.clone()),
+        )];
+        if let Some(into) = &self.into {
+            vec.push(("into".to_string(), toml::Value::from(into.as_str())));
+        }
+        if self.password_prompt {
+            vec.push(("password_prompt".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.overwrite {
+            vec.push(("overwrite".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.list {
+            vec.push(("list".to_string(), toml::Value::Boolean(true)));
+        }
+        vec.into_iter()
+    }
+}
+
+impl Unzip {
+    fn unzip(&self, filed_map: &Table, config_obj: &Table) {
+        let current_dir = std::env::current_dir().unwrap();
+        let archive = current_dir.join(filed_map.get("archive").unwrap().as_str().unwrap());
+        if !archive.is_file() {
+            eprintln!("{} {}", "Error 压缩包不存在:".red(), archive.display());
+            std::process::exit(1);
+        }
+        if filed_map.get("list").is_some_and(|v| v.as_bool().unwrap()) {
+            Unzip::list(filed_map, &archive);
+            return;
+        }
+        let target_dir = Unzip::target_dir(filed_map, config_obj);
+        let password = Unzip::password(filed_map, &archive);
+        let entries = match list_extract_entries(&archive, &password) {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!("{} {}", "Error 读取压缩包失败:".red(), err);
+                std::process::exit(1);
+            }
+        };
+
+        // 先检查全部条目 存在会解压到目标文件夹之外的条目时不解压任何文件
+        let unsafe_names = entries
+            .iter()
+            .filter(|entry| safe_relative_path(&entry.name).is_none())
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<&str>>();
+        if !unsafe_names.is_empty() {
+            eprintln!("{}", "Error 压缩包中存在不安全的路径，已拒绝解压:".red());
+            for name in unsafe_names {
+                eprintln!("  {}", name);
+            }
+            std::process::exit(1);
+        }
+        for entry in entries.iter().filter(|entry| entry.is_symlink) {
+            println!("{} 跳过符号链接: {}", "Warning".yellow(), entry.name);
+        }
+        for entry in entries.iter().filter(|entry| entry.is_dir) {
+            let path = target_dir.join(safe_relative_path(&entry.name).unwrap());
+            std::fs::create_dir_all(path).unwrap();
+        }
+
+        let overwrite = filed_map
+            .get("overwrite")
+            .is_some_and(|v| v.as_bool().unwrap());
+        let mut extracted = Vec::new();
+        let mut skipped = Vec::new();
+        let result = extract_files(&archive, &password, |name, data| {
+            let path = target_dir.join(safe_relative_path(name).unwrap());
+            if path.exists() && !overwrite {
+                skipped.push(name.to_string());
+                // 7z 固实压缩需要按顺序解压 跳过的内容也要读取
+                io::copy(data, &mut io::sink())?;
+                return Ok(());
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            io::copy(data, &mut File::create(&path)?)?;
+            extracted.push(name.to_string());
+            Ok(())
+        });
+        if let Err(err) = result {
+            eprintln!("{} {}", "Error 解压失败:".red(), err);
+            std::process::exit(1);
+        }
+
+        println!("解压到: {}", target_dir.display().to_string().green());
+        for name in &extracted {
+            println!("  {}", name);
+        }
+        println!("共解压 {} 个文件", extracted.len().to_string().green());
+        if !skipped.is_empty() {
+            println!(
+                "{}",
+                "以下文件已存在，已跳过(使用 --overwrite 覆盖):".yellow()
+            );
+            for name in &skipped {
+                println!("  {}", name.yellow());
+            }
+        }
+    }
+
+    /// 列出文件名和读取时使用的编码，以及不支持 UTF-8 的旧版工具中显示的文件名
+    fn list(filed_map: &Table, archive: &Path) {
+        if is_7z(archive).unwrap_or(false) {
+            let password = Unzip::password(filed_map, archive);
+            let entries = match list_extract_entries(archive, &password) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    eprintln!("{} {}", "Error 读取压缩包失败:".red(), err);
+                    std::process::exit(1);
+                }
+            };
+            for entry in entries {
+                println!("{} {}", "[7z UTF-16]".blue(), entry.name);
+            }
+            return;
+        }
+        let entries = match read_central_directory(archive) {
+            Ok((entries, _)) => entries,
+            Err(err) => {
+                eprintln!("{} {}", "Error 读取压缩包失败:".red(), err);
+                std::process::exit(1);
+            }
+        };
+        for entry in entries {
+            let (name, source) = entry.read_name();
+            let legacy_name = entry.legacy_name();
+            if legacy_name == name {
+                println!("{} {}", format!("[{}]", source).blue(), name);
+            } else {
+                println!(
+                    "{} {} {}",
+                    format!("[{}]", source).blue(),
+                    name,
+                    format!("(旧版工具中显示为: {})", legacy_name).yellow()
+                );
+            }
+        }
+    }
+
+    /// 解压到的文件夹
+    /// `--into` 为课程序号时使用工作目录中的课程文件夹，否则作为路径
+    /// 未指定时使用当前所在的课程文件夹，不在课程文件夹中时报错
+    fn target_dir(filed_map: &Table, config_obj: &Table) -> PathBuf {
+        let current_dir = std::env::current_dir().unwrap();
+        let workspace = config_obj
+            .get("new")
+            .and_then(|new| new.as_table()?.get("workspace")?.as_str())
+            .unwrap_or_default();
+        let projects = list_projects(Path::new(workspace));
+        if let Some(into) = filed_map.get("into").map(|v| v.as_str().unwrap()) {
+            let Ok(number) = into.parse::<i64>() else {
+                return current_dir.join(into);
+            };
+            return match projects.into_iter().find(|(n, _, _)| *n == number) {
+                Some((_, _, path)) => path,
+                None => {
+                    eprintln!("{} {}", "Error 工作目录中没有这个课程:".red(), number);
+                    std::process::exit(1);
+                }
+            };
+        }
+        let project_dir = current_dir.ancestors().find(|dir| {
+            dir.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(parse_project_name)
+                .is_some()
+        });
+        match project_dir {
+            Some(project_dir) => project_dir.to_path_buf(),
+            // 不猜测解压到哪个课程 避免解压到错误的课程文件夹中
+            None => {
+                eprintln!(
+                    "{}",
+                    "Error 当前不在课程文件夹中，请使用 --into 指定课程序号或者文件夹".red()
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// 解压密码，`--password-prompt` 或者压缩包加密时从终端读取
+    fn password(filed_map: &Table, archive: &Path) -> String {
+        let is_prompt = filed_map
+            .get("password_prompt")
+            .is_some_and(|v| v.as_bool().unwrap());
+        if is_prompt || needs_password(archive).unwrap_or(false) {
+            return rpassword::prompt_password("请输入解压密码: ").unwrap();
+        }
+        String::new()
+    }
+}
+
+/// 压缩包中的文件名转为相对路径，绝对路径或者包含 `..` 时返回 `None`
+fn safe_relative_path(name: &str) -> Option<PathBuf> {
+    if name.contains('\0') {
+        return None;
+    }
+    let mut path = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if path.as_os_str().is_empty() {
+        return None;
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reject_unsafe_paths() {
+        assert_eq!(
+            safe_relative_path("src/main.cpp"),
+            Some(PathBuf::from("src/main.cpp"))
+        );
+        assert_eq!(safe_relative_path("./a.txt"), Some(PathBuf::from("a.txt")));
+        assert_eq!(safe_relative_path("../a.txt"), None);
+        assert_eq!(safe_relative_path("src/../../a.txt"), None);
+        assert_eq!(safe_relative_path("/etc/passwd"), None);
+    }
+}
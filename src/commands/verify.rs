@@ -0,0 +1,285 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use colored::Colorize;
+use toml::Table;
+
+use crate::tools::archive::{read_comment, read_entries, EntryInfo};
+use crate::tools::files::format_size;
+use crate::tools::manifest::{read_manifest_file, Manifest, DELETED_FILE, MANIFEST_JSON};
+
+use super::MyCommand;
+
+/// 压缩密码与 zip 命令共用 `[zip]` 中的配置
+const TABLE_NAME: &str = "zip";
+
+#[derive(Parser, Debug)]
+/// 根据压缩包中的提交清单校验压缩包
+/// 检查清单中每个文件的大小和 SHA-256，以及缺少或多出的文件
+pub struct Verify {
+    /// 需要校验的压缩包路径
+    pub archive: String,
+    #[arg(long)]
+    /// 从终端输入压缩密码(不回显)，默认使用配置中的密码
+    pub password_prompt: bool,
+}
+
+impl MyCommand for &Verify {
+    fn run(&self, config_obj: &toml::Table) {
+        let filed_map = self.parse_field(config_obj);
+        let code = Verify::verify(&filed_map);
+        if code != 0 {
+            std::process::exit(code);
+        }
+    }
+    fn get_global_filed_map(&self, config_obj: &Table) -> Table {
+        super::get_global_filed_map(config_obj)
+    }
+
+    fn get_filed_map(&self, config_obj: &Table) -> Table {
+        match config_obj.get(TABLE_NAME) {
+            Some(table) => {
+                let table = table.as_table().unwrap();
+                table.clone()
+            }
+            None => Table::new(),
+        }
+    }
+}
+
+impl IntoIterator for &Verify {
+    type Item = (String, toml::Value);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+    fn into_iter(self) -> Self::IntoIter {
+        let mut vec = Vec::new();
+        vec.push((
+            "archive".to_string(),
+            toml::Value::String(self.archive.clone()),
+        ));
+        if self.password_prompt {
+            vec.push(("password_prompt".to_string(), toml::Value::Boolean(true)));
+        }
+        vec.into_iter()
+    }
+}
+
+impl Verify {
+    /// 校验压缩包并输出结果，返回退出码，校验通过时为 0
+    fn verify(filed_map: &Table) -> i32 {
+        let archive = PathBuf::from(filed_map.get("archive").unwrap().as_str().unwrap());
+        if !archive.is_file() {
+            eprintln!("{} {}", "Error 压缩包不存在:".red(), archive.display());
+            return 1;
+        }
+        let password = Verify::password(filed_map);
+        let Some((manifest_name, manifest)) = Verify::read_manifest(&archive, &password) else {
+            return 1;
+        };
+        println!("清单: {}", manifest_name);
+        Verify::print_header(&manifest);
+
+        let entries = match read_entries(&archive, &password) {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!("{} {}", "Error 读取压缩包失败:".red(), err);
+                return 1;
+            }
+        };
+        let problems = Verify::compare(&manifest, &manifest_name, &entries);
+        if problems.is_empty() {
+            println!(
+                "{} {} 个文件与清单一致",
+                "校验通过".green(),
+                manifest.files.len()
+            );
+            return 0;
+        }
+        eprintln!("{}", "Error 压缩包与清单不一致:".red());
+        for problem in &problems {
+            eprintln!("  {}", problem);
+        }
+        1
+    }
+
+    fn password(filed_map: &Table) -> String {
+        let is_prompt = filed_map
+            .get("password_prompt")
+            .is_some_and(|v| v.as_bool().unwrap());
+        if is_prompt {
+            return rpassword::prompt_password("请输入压缩密码: ").unwrap();
+        }
+        match filed_map.get("password") {
+            Some(password) => password.as_str().unwrap().to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// 读取压缩包中的清单文件，没有清单文件时使用 zip 注释中的清单
+    /// 读取失败或者没有清单时输出错误并返回 `None`
+    fn read_manifest(archive: &Path, password: &str) -> Option<(String, Manifest)> {
+        match read_manifest_file(archive, password) {
+            Ok(Some(manifest)) => return Some(manifest),
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("{} {}", "Error 读取清单失败:".red(), err);
+                return None;
+            }
+        }
+        let comment = read_comment(archive).unwrap_or_default();
+        match Manifest::parse(MANIFEST_JSON, comment.as_bytes()) {
+            Ok(manifest) => Some(("压缩包注释".to_string(), manifest)),
+            Err(_) => {
+                eprintln!("{}", "Error 压缩包中没有提交清单".red());
+                None
+            }
+        }
+    }
+
+    fn print_header(manifest: &Manifest) {
+        println!("提交人: {} {}", manifest.class_name, manifest.user_name);
+        if let (Some(number), Some(name)) = (manifest.courses_number, &manifest.course_name) {
+            println!("课程: {}-{}", number, name);
+        }
+        if let Some(created_at) = &manifest.created_at {
+            println!("生成时间: {}", created_at);
+        }
+        println!("生成工具: {}", manifest.tool);
+        if let Some(base) = &manifest.base {
+            println!("增量提交，基准: {}", base);
+        }
+        if let Some(commit) = &manifest.commit {
+            println!("git 提交: {}", commit);
+        }
+    }
+
+    /// 比较清单与压缩包中的文件 返回不一致的描述
+    fn compare(manifest: &Manifest, manifest_name: &str, entries: &[EntryInfo]) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut actual = BTreeMap::new();
+        for entry in entries {
+            if let Some(error) = &entry.error {
+                problems.push(format!("{} 解压失败: {}", entry.name, error));
+                continue;
+            }
+            if entry.is_dir || entry.name == manifest_name {
+                continue;
+            }
+            let Some(name) = manifest.relative_path(&entry.name) else {
+                problems.push(format!("顶层文件夹之外的文件: {}", entry.name));
+                continue;
+            };
+            // 增量提交中记录删除文件的列表不在清单中
+            if manifest.base.is_some() && name == DELETED_FILE {
+                continue;
+            }
+            actual.insert(name, entry);
+        }
+        for file in &manifest.files {
+            let Some(entry) = actual.remove(file.path.as_str()) else {
+                problems.push(format!("缺少文件: {}", file.path));
+                continue;
+            };
+            if entry.size != file.size {
+                problems.push(format!(
+                    "{} 大小不一致: 清单 {} 实际 {}",
+                    file.path,
+                    format_size(file.size),
+                    format_size(entry.size)
+                ));
+            } else if entry.sha256 != file.sha256 {
+                problems.push(format!("{} SHA-256 不一致", file.path));
+            }
+        }
+        for name in actual.keys() {
+            problems.push(format!("清单中没有的文件: {}", name));
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tools::archive::{ArchiveOptions, ArchiveWriter, Encryption};
+    use crate::tools::compression::CompressionRules;
+    use crate::tools::files::{sha256_file, TempDir};
+    use crate::tools::manifest::ManifestFile;
+    use crate::tools::zip_names::FilenameEncoding;
+
+    /// 生成只有 main.cpp 的压缩包，清单中记录的文件信息为 `file`
+    fn write_archive(path: &Path, content: &[u8], file: &ManifestFile) -> Manifest {
+        let archive_options = ArchiveOptions {
+            encryption: Encryption::Aes128,
+            password: String::new(),
+            reproducible: false,
+            filename_encoding: FilenameEncoding::Utf8,
+            compression: CompressionRules::from_table(None, None).unwrap(),
+        };
+        let manifest = Manifest {
+            user_name: "张三".to_string(),
+            class_name: "CR49".to_string(),
+            tool: "cr-cli".to_string(),
+            files: vec![file.clone()],
+            ..Default::default()
+        };
+        let mut writer = ArchiveWriter::create(path, &archive_options).unwrap();
+        writer.add_bytes("main.cpp", content).unwrap();
+        writer
+            .add_bytes(MANIFEST_JSON, manifest.to_json().as_bytes())
+            .unwrap();
+        writer.finish().unwrap();
+        manifest
+    }
+
+    /// 校验压缩包，返回退出码
+    fn verify(archive: &Path) -> i32 {
+        let mut filed_map = Table::new();
+        filed_map.insert("archive".to_string(), archive.to_str().unwrap().into());
+        Verify::verify(&filed_map)
+    }
+
+    /// 压缩包与清单不一致的描述
+    fn problems(archive: &Path, manifest: &Manifest) -> Vec<String> {
+        let entries = read_entries(archive, "").unwrap();
+        Verify::compare(manifest, MANIFEST_JSON, &entries)
+    }
+
+    #[test]
+    fn verify_against_manifest() {
+        let temp = TempDir::new("verify").unwrap();
+        let content = b"int main() {}\n";
+        let source = temp.0.join("main.cpp");
+        std::fs::write(&source, content).unwrap();
+        let file = ManifestFile {
+            path: "main.cpp".to_string(),
+            size: content.len() as u64,
+            sha256: sha256_file(&source).unwrap(),
+        };
+
+        let archive = temp.0.join("ok.zip");
+        let manifest = write_archive(&archive, content, &file);
+        assert_eq!(verify(&archive), 0);
+        assert!(problems(&archive, &manifest).is_empty());
+
+        let archive = temp.0.join("size.zip");
+        let manifest = write_archive(&archive, b"int main() { return 0; }\n", &file);
+        assert_eq!(verify(&archive), 1);
+        let size_problems = problems(&archive, &manifest);
+        assert_eq!(size_problems.len(), 1);
+        assert!(size_problems[0].starts_with("main.cpp 大小不一致"));
+
+        // 大小相同 内容不同
+        let archive = temp.0.join("sha256.zip");
+        let manifest = write_archive(&archive, b"int main() {}\r", &file);
+        assert_eq!(verify(&archive), 1);
+        assert_eq!(
+            problems(&archive, &manifest),
+            vec!["main.cpp SHA-256 不一致"]
+        );
+
+        assert_eq!(verify(&temp.0.join("missing.zip")), 1);
+    }
+}
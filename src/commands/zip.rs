@@ -1,17 +1,40 @@
-use std::{io::{Read, Write}, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use colored::Colorize;
+use glob::Pattern;
 use toml::Table;
 use walkdir::WalkDir;
-use zip::ZipWriter;
 
-use crate::config::get_default_zip_file_name;
+use crate::config::{
+    get_default_zip_file_name, get_name_template, get_zip_file_name_prefixes, render_zip_file_name,
+    DEFAULT_COURSE_NAME_TEMPLATE, DEFAULT_NAME_TEMPLATE,
+};
+use crate::tools::archive::{
+    read_comment, read_entries, ArchiveOptions, ArchiveWriter, Encryption,
+};
+use crate::tools::compression::CompressionRules;
+use crate::tools::files::{format_size, parse_size, sha256_file, TempDir};
+use crate::tools::git::Repository;
+use crate::tools::guard::{GuardIssue, GuardRules};
+use crate::tools::manifest::{
+    read_manifest_file, Manifest, ManifestFile, DELETED_FILE, MANIFEST_JSON, MANIFEST_TOML,
+};
+use crate::tools::presets::{self, Preset, PRESETS};
+use crate::tools::zip_names::{check_without_zip64, FilenameEncoding};
 
+use super::new::{find_project, list_projects, parse_project_name};
 use super::MyCommand;
 
 const TABLE_NAME: &str = "zip";
 
+/// `--since-last` 对应的 `since` 字段值
+const SINCE_LAST: &str = "last";
+
 #[derive(Parser, Debug)]
 /// 关于压缩的命令
 /// 命令可以帮你压缩文件夹 并生成默认班级格式的压缩文件
@@ -20,10 +43,83 @@ pub struct Zip {
     /// 需要忽略的文件夹名称，例如输入: .git .vs Debug
     /// 将会在进行压缩时忽略这些文件夹
     pub ignore: Option<Vec<String>>,
-    #[arg(long, short)]
+    #[arg(long, short, required_unless_present_any = ["course", "range", "all_unsubmitted"])]
     /// 需要压缩的文件夹路径,例如: /home/username/workspace
     /// 将会递归的压缩这个文件夹，生成一个压缩文件
-    pub dir_path: String,
+    pub dir_path: Option<String>,
+    #[arg(long)]
+    /// 压缩完成后重新打开压缩包，校验每个文件的CRC以及文件列表和大小
+    pub verify: bool,
+    #[arg(long, short)]
+    /// 需要排除的文件匹配规则，例如输入: "*.log" "**/*.pdb"
+    pub exclude: Option<Vec<String>>,
+    #[arg(long, short)]
+    /// 额外放入压缩包的文件夹之外的文件或文件夹，格式为 `源路径[:压缩包中的路径]`
+    /// 例如输入: ../笔记.md D:\报告\实验一.pdf:docs/报告.pdf
+    pub add: Option<Vec<String>>,
+    #[arg(long)]
+    /// 不根据项目类型自动排除构建产物
+    pub no_presets: bool,
+    #[arg(long)]
+    /// 生成可复现的压缩包：固定条目顺序、时间戳、权限和压缩参数，相同的输入得到相同的文件
+    pub reproducible: bool,
+    #[arg(long, value_parser = clap::value_parser!(i64).range(0..=9))]
+    /// 压缩等级 0-9，0 最快，9 压缩率最高，默认使用每种压缩方式的默认等级
+    pub level: Option<i64>,
+    #[arg(long)]
+    /// 存在过大的文件、可执行文件或目标文件、IDE缓存时终止压缩
+    pub strict: bool,
+    #[arg(long)]
+    /// 逐个询问是否排除过大的文件、可执行文件或目标文件、IDE缓存
+    pub interactive: bool,
+    #[arg(long)]
+    /// 从终端输入压缩密码(不回显)，密码不会写入配置文件
+    pub password_prompt: bool,
+    #[arg(long)]
+    /// 不在压缩包中写入提交清单
+    pub no_manifest: bool,
+    #[arg(long)]
+    /// 把所有内容放在压缩包中一个与压缩包同名的顶层文件夹中
+    pub wrap: bool,
+    #[arg(long, conflicts_with = "since")]
+    /// 增量提交：只压缩自上一次提交以来新增和修改的文件，删除的文件记录在 DELETED.txt 中
+    pub since_last: bool,
+    #[arg(long)]
+    /// 增量提交：与指定的压缩包或日期(例如 2024-05-20)的提交进行比较
+    pub since: Option<String>,
+    #[arg(long)]
+    /// 只压缩 git 仓库 HEAD 提交中的文件，使用提交时的内容，提交哈希写入压缩包注释
+    pub git_tracked: bool,
+    #[arg(long, requires = "git_tracked")]
+    /// 与 --git-tracked 一起使用：压缩被 git 跟踪的文件在工作区中的当前内容
+    pub worktree: bool,
+    #[arg(long, conflicts_with_all = ["dir_path", "since", "since_last"])]
+    /// 批量压缩：工作目录中指定序号的课程，可以重复使用，例如: --course 12 --course 13
+    pub course: Option<Vec<i64>>,
+    #[arg(long, conflicts_with_all = ["dir_path", "since", "since_last"])]
+    /// 批量压缩：工作目录中序号在范围内的课程(包含两端)，例如: 10..14
+    pub range: Option<String>,
+    #[arg(long, conflicts_with_all = ["dir_path", "since", "since_last"])]
+    /// 批量压缩：工作目录中还没有提交过的课程
+    pub all_unsubmitted: bool,
+    #[arg(long)]
+    /// 批量压缩时只生成一个压缩包，每个课程一个文件夹，默认每个课程生成一个压缩包
+    pub combined: bool,
+    #[arg(long)]
+    /// 只列出将要压缩的文件以及被排除的文件和原因，不生成压缩包
+    pub dry_run: bool,
+    #[arg(long, short)]
+    /// 压缩包的输出路径，可以是文件路径或者文件夹，默认为当前路径
+    pub output: Option<String>,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    /// `--dry-run` 的输出格式
+    pub format: OutputFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
 }
 
 impl MyCommand for &Zip {
@@ -62,20 +158,231 @@ impl IntoIterator for &Zip {
                 ),
             ));
         }
-        vec.push(("dir_path".to_string(), toml::Value::String(self.dir_path.clone())));
+        if let Some(dir_path) = &self.dir_path {
+            vec.push((
+                "dir_path".to_string(),
+                toml::Value::String(dir_path.clone()),
+            ));
+        }
+        // 只有显式指定时才覆盖配置文件中的值
+        if self.verify {
+            vec.push(("verify".to_string(), toml::Value::Boolean(true)));
+        }
+        if let Some(exclude) = &self.exclude {
+            vec.push((
+                "exclude".to_string(),
+                toml::Value::Array(
+                    exclude
+                        .iter()
+                        .map(|s| toml::Value::from(s.as_str()))
+                        .collect(),
+                ),
+            ));
+        }
+        if let Some(add) = &self.add {
+            vec.push((
+                "add".to_string(),
+                toml::Value::Array(add.iter().map(|s| toml::Value::from(s.as_str())).collect()),
+            ));
+        }
+        if let Some(output) = &self.output {
+            vec.push(("output".to_string(), toml::Value::from(output.as_str())));
+        }
+        if self.no_presets {
+            vec.push(("no_presets".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.reproducible {
+            vec.push(("reproducible".to_string(), toml::Value::Boolean(true)));
+        }
+        if let Some(level) = self.level {
+            vec.push(("level".to_string(), toml::Value::from(level)));
+        }
+        if self.strict {
+            vec.push(("strict".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.interactive {
+            vec.push(("interactive".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.password_prompt {
+            vec.push(("password_prompt".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.no_manifest {
+            vec.push(("no_manifest".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.wrap {
+            vec.push(("wrap".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.since_last {
+            vec.push(("since".to_string(), toml::Value::from(SINCE_LAST)));
+        }
+        if let Some(since) = &self.since {
+            vec.push(("since".to_string(), toml::Value::from(since.as_str())));
+        }
+        if self.git_tracked {
+            vec.push(("git_tracked".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.worktree {
+            vec.push(("worktree".to_string(), toml::Value::Boolean(true)));
+        }
+        if let Some(course) = &self.course {
+            vec.push((
+                "course".to_string(),
+                toml::Value::Array(course.iter().map(|n| toml::Value::from(*n)).collect()),
+            ));
+        }
+        if let Some(range) = &self.range {
+            vec.push(("range".to_string(), toml::Value::from(range.as_str())));
+        }
+        if self.all_unsubmitted {
+            vec.push(("all_unsubmitted".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.combined {
+            vec.push(("combined".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.dry_run {
+            vec.push(("dry_run".to_string(), toml::Value::Boolean(true)));
+        }
+        if self.format == OutputFormat::Json {
+            vec.push(("format".to_string(), toml::Value::from("json")));
+        }
 
         vec.into_iter()
     }
 }
 
+/// 不经过命令行参数，直接由配置文件合成 zip 命令的字段
+/// 供其他命令(例如 `mail -a`)调用压缩逻辑时使用
+pub fn config_filed_map(config_obj: &Table) -> Table {
+    let mut filed_map = super::get_global_filed_map(config_obj);
+    if let Some(table) = config_obj.get(TABLE_NAME) {
+        filed_map.extend(table.as_table().unwrap().clone());
+    }
+    filed_map
+}
+
+/// 写入压缩包中的一个条目
+pub struct PackedEntry {
+    /// 压缩包中的路径 unix风格
+    pub name: String,
+    /// 对应的源文件路径
+    pub source: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+/// 压缩时被排除的路径
+pub struct Excluded {
+    /// 相对于压缩文件夹的路径 unix风格
+    pub name: String,
+    pub rule: ExcludeRule,
+}
+
+/// 排除一个路径的规则
+pub enum ExcludeRule {
+    /// 命中了 `ignore` 列表中的名称
+    Ignore(String),
+    /// 命中了 `exclude` 中的匹配规则
+    Pattern(String),
+    /// 命中了自动检测到的项目类型的构建产物规则 (预设名称, 规则)
+    Preset(&'static str, &'static str),
+    /// 超过了 `max_file_size` 限制
+    SizeLimit(u64),
+    /// 本次或之前生成的提交压缩包
+    Archive,
+    /// 压缩前检查时手动排除
+    Guard(String),
+    /// 与程序生成的文件(例如提交清单)重名
+    Generated,
+    /// 增量提交时自上一次提交以来未修改
+    Unchanged,
+    /// `--git-tracked --worktree` 时没有被 git 跟踪
+    Untracked,
+}
+
+/// `--git-tracked` 时压缩的 git 仓库
+struct GitSource {
+    repo: Repository,
+    /// 压缩的文件夹相对于仓库根目录的路径 unix风格 根目录时为空
+    prefix: String,
+    /// HEAD 的提交，`--worktree` 并且有未提交的修改时以 `-dirty` 结尾
+    commit: Option<String>,
+    /// 导出 HEAD 中文件的临时文件夹，`--worktree` 时为 `None`
+    export: Option<TempDir>,
+}
+
+/// 增量提交相对于上一次提交的变化
+pub struct Increment {
+    /// 作为基准的上一次提交的压缩包名称
+    pub base: String,
+    /// 未修改、不放入压缩包的文件
+    pub unchanged: Vec<ManifestFile>,
+    /// 上一次提交之后删除的文件
+    pub deleted: Vec<String>,
+}
+
+impl ExcludeRule {
+    fn kind(&self) -> &'static str {
+        match self {
+            ExcludeRule::Ignore(_) => "ignore",
+            ExcludeRule::Pattern(_) => "pattern",
+            ExcludeRule::Preset(..) => "preset",
+            ExcludeRule::SizeLimit(_) => "size_limit",
+            ExcludeRule::Archive => "archive",
+            ExcludeRule::Guard(_) => "guard",
+            ExcludeRule::Generated => "generated",
+            ExcludeRule::Unchanged => "unchanged",
+            ExcludeRule::Untracked => "untracked",
+        }
+    }
+
+    fn detail(&self) -> String {
+        match self {
+            ExcludeRule::Ignore(name) => name.clone(),
+            ExcludeRule::Pattern(pattern) => pattern.clone(),
+            ExcludeRule::Preset(name, pattern) => format!("{}: {}", name, pattern),
+            ExcludeRule::SizeLimit(limit) => limit.to_string(),
+            ExcludeRule::Archive => String::new(),
+            ExcludeRule::Guard(issue) => issue.clone(),
+            ExcludeRule::Generated | ExcludeRule::Unchanged | ExcludeRule::Untracked => {
+                String::new()
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ExcludeRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExcludeRule::Ignore(name) => write!(f, "忽略列表: {}", name),
+            ExcludeRule::Pattern(pattern) => write!(f, "匹配规则: {}", pattern),
+            ExcludeRule::Preset(name, pattern) => write!(f, "构建产物预设 {}: {}", name, pattern),
+            ExcludeRule::SizeLimit(limit) => write!(f, "超过大小限制: {}", format_size(*limit)),
+            ExcludeRule::Archive => write!(f, "生成的压缩包"),
+            ExcludeRule::Guard(issue) => write!(f, "手动排除 {}", issue),
+            ExcludeRule::Generated => write!(f, "与生成的文件重名"),
+            ExcludeRule::Unchanged => write!(f, "自上一次提交以来未修改"),
+            ExcludeRule::Untracked => write!(f, "未被 git 跟踪"),
+        }
+    }
+}
+
+fn get_string_list(filed_map: &Table, key: &str) -> Vec<String> {
+    match filed_map.get(key) {
+        Some(list) => list
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_str().unwrap().to_string())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
 // 业务逻辑
 impl Zip {
-    pub fn _zip(
-        dir_path_str: &str,
-        ignore_dir: &mut Vec<String>,
-        file_name_str: &str,
-        config_obj: &Table,
-    ) {
+    /// 压缩文件夹，返回生成的压缩包路径，`--dry-run` 时返回 `None`
+    pub fn _zip(filed_map: &Table, config_obj: &Table) -> Option<PathBuf> {
+        let dir_path_str = filed_map.get("dir_path").unwrap().as_str().unwrap();
         let mut dir_path = Path::new(dir_path_str).to_owned();
         let current_dir = std::env::current_dir().unwrap();
         if dir_path.is_relative() {
@@ -92,76 +399,1702 @@ impl Zip {
             );
             std::process::exit(1);
         }
+        let zip_file = Zip::output_path(filed_map, config_obj);
+        let file_name_str = zip_file.file_name().unwrap().to_str().unwrap().to_string();
+        // 先写入临时文件 完成后再重命名 避免留下写了一半的压缩包
+        let part_file = zip_file.with_file_name(format!(".{}.part", file_name_str));
+        let archive_prefixes = get_zip_file_name_prefixes(config_obj);
+        // 只压缩 git 跟踪的文件时 HEAD 中的文件先导出到临时文件夹
+        let git = Zip::git_source(&dir_path, filed_map);
+        let source_dir = match git.as_ref().and_then(|git| git.export.as_ref()) {
+            Some(export) => export.0.clone(),
+            None => dir_path.clone(),
+        };
+        let (mut packed, mut excluded) = Zip::collect_entries(
+            &source_dir,
+            filed_map,
+            &[&zip_file, &part_file],
+            &archive_prefixes,
+        );
+        if let Some(git) = git.as_ref().filter(|git| git.export.is_none()) {
+            Zip::retain_tracked(git, &mut packed, &mut excluded);
+        }
+        // 批量压缩到一个压缩包时 只保留选中的课程文件夹
+        if filed_map.contains_key("course_dirs") {
+            let course_dirs = get_string_list(filed_map, "course_dirs");
+            let is_selected = |name: &str| {
+                course_dirs
+                    .iter()
+                    .any(|dir| dir == name.split('/').next().unwrap())
+            };
+            packed.retain(|entry| is_selected(&entry.name));
+            excluded.retain(|item| is_selected(&item.name));
+        }
+        Zip::add_extra_files(&mut packed, &dir_path, filed_map);
+        // 压缩包根目录中由程序生成的文件 源文件夹中的同名文件不会被压缩
+        let manifest_name = Zip::manifest_name(filed_map);
+        let is_incremental = filed_map.contains_key("since");
+        let mut generated = manifest_name.into_iter().collect::<Vec<&str>>();
+        if is_incremental {
+            generated.push(DELETED_FILE);
+        }
+        for name in &generated {
+            if let Some(index) = packed.iter().position(|entry| entry.name == *name) {
+                packed.remove(index);
+                excluded.push(Excluded {
+                    name: name.to_string(),
+                    rule: ExcludeRule::Generated,
+                });
+            }
+        }
+        // 读取加密的上一次提交时需要密码 之后压缩时使用同一个密码
+        let mut password = None;
+        let increment = Zip::previous_submission(filed_map, config_obj, &zip_file, &mut password)
+            .map(|(base, previous)| Zip::increment(&mut packed, &mut excluded, base, &previous));
+        let warnings = Zip::guard_check(&packed, filed_map);
+        let filename_encoding = Zip::filename_encoding(filed_map);
+        if Zip::encryption(filed_map) != Encryption::SevenZAes256 {
+            for entry in &packed {
+                if filename_encoding.is_unencodable(&entry.name) {
+                    // 输出到 stderr 避免影响 `--format json` 的输出
+                    eprintln!(
+                        "{} 文件名无法使用 GBK 编码，将保留为 UTF-8: {}",
+                        "Warning".yellow(),
+                        entry.name
+                    );
+                }
+            }
+        }
+        if let Some(true) = filed_map.get("dry_run").map(|v| v.as_bool().unwrap()) {
+            let is_json = filed_map.get("format").and_then(|v| v.as_str()) == Some("json");
+            if is_json {
+                let output = Zip::dry_run_json(
+                    &dir_path,
+                    &zip_file,
+                    &packed,
+                    &excluded,
+                    &warnings,
+                    increment.as_ref(),
+                );
+                println!("{}", serde_json::to_string_pretty(&output).unwrap());
+            } else {
+                Zip::print_dry_run(
+                    &dir_path,
+                    &zip_file,
+                    &packed,
+                    &excluded,
+                    &warnings,
+                    increment.as_ref(),
+                );
+            }
+            return None;
+        }
+        Zip::guard(&mut packed, &mut excluded, warnings, filed_map);
+        if Zip::encryption(filed_map) != Encryption::SevenZAes256 {
+            // 额外的条目为提交清单
+            let total_size = packed.iter().map(|entry| entry.size).sum();
+            if let Err(err) = check_without_zip64(filename_encoding, packed.len() + 1, total_size) {
+                eprintln!("{} {}", "Error".red(), err);
+                std::process::exit(1);
+            }
+        }
+        let archive_options = ArchiveOptions {
+            encryption: Zip::encryption(filed_map),
+            password: password.unwrap_or_else(|| Zip::password(filed_map)),
+            reproducible: filed_map
+                .get("reproducible")
+                .is_some_and(|v| v.as_bool().unwrap()),
+            filename_encoding,
+            compression: Zip::compression_rules(filed_map),
+        };
+        if archive_options.reproducible && !archive_options.password.is_empty() {
+            println!(
+                "{} 加密使用随机盐值，加密后的压缩包无法做到逐字节一致",
+                "Warning".yellow()
+            );
+        }
+        if let Some(parent) = zip_file.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
         // 3. 复制文件
-        let zip_file = std::fs::File::create(current_dir.join(&file_name_str)).unwrap();
-        let mut zip_writer = ZipWriter::new(zip_file);
-        let mut options = zip::write::SimpleFileOptions::default()
-            .compression_method(zip::CompressionMethod::Deflated);
-        // 如果config中含有密码，则使用
-        let zip_table = config_obj.get("zip").unwrap().as_table().unwrap();
-        if let Some(password) = zip_table.get("password") {
-            // 如果密码存在
-            let password = password.as_str().unwrap();
-            if !password.is_empty() {
-                // 如果不是空字符串
-                options = options.with_aes_encryption(zip::AesMode::Aes128, password);
-            }
-        }
-        // 遍历当前文件夹下所有文件
-        let it = WalkDir::new(&dir_path).into_iter();
-        let mut buf = Vec::new();
-
-        for entry in it {
+        let root = Zip::wrap_root(filed_map, &zip_file);
+        let archive_name = |name: &str| match &root {
+            Some(root) => format!("{}/{}", root, name),
+            None => name.to_string(),
+        };
+        let mut writer = ArchiveWriter::create(&part_file, &archive_options).unwrap();
+        if let Some(root) = &root {
+            writer.add_directory(root, &dir_path).unwrap();
+        }
+        for entry in &packed {
+            if entry.is_dir {
+                writer
+                    .add_directory(&archive_name(&entry.name), &entry.source)
+                    .unwrap();
+            } else {
+                writer
+                    .add_file(&archive_name(&entry.name), &entry.source)
+                    .unwrap();
+            }
+        }
+        // 4. 写入提交清单 并把同样的信息写入压缩包注释
+        if let Some(increment) = &increment {
+            let content = increment
+                .deleted
+                .iter()
+                .map(|name| format!("{}\n", name))
+                .collect::<String>();
+            writer
+                .add_bytes(&archive_name(DELETED_FILE), content.as_bytes())
+                .unwrap();
+        }
+        if let Some(manifest_name) = manifest_name {
+            let mut manifest =
+                Zip::build_manifest(&dir_path, &packed, config_obj, &archive_options);
+            if let Some(increment) = increment {
+                manifest.base = Some(increment.base);
+                manifest.unchanged = increment.unchanged;
+            }
+            manifest.commit = git.as_ref().and_then(|git| git.commit.clone());
+            manifest.root = root.clone();
+            let content = match manifest_name {
+                MANIFEST_TOML => manifest.to_toml(),
+                _ => manifest.to_json(),
+            };
+            writer
+                .add_bytes(&archive_name(manifest_name), content.as_bytes())
+                .unwrap();
+            writer.set_comment(&Zip::manifest_comment(&manifest));
+        } else if let Some(commit) = git.as_ref().and_then(|git| git.commit.as_ref()) {
+            writer.set_comment(&format!("commit {}", commit));
+        }
+        writer.finish().unwrap();
+
+        // 校验压缩包 校验失败时不会覆盖已有的压缩包
+        let is_verify = match filed_map.get("verify") {
+            Some(verify) => verify.as_bool().unwrap(),
+            None => false,
+        };
+        if is_verify {
+            let problems = Zip::verify(
+                &part_file,
+                &archive_options.password,
+                &packed,
+                &generated,
+                root.as_deref(),
+            );
+            if !problems.is_empty() {
+                for problem in &problems {
+                    eprintln!("{} {}", "Error 校验失败:".red(), problem);
+                }
+                let _ = std::fs::remove_file(&part_file);
+                std::process::exit(1);
+            }
+            println!("{} 共校验 {} 个条目", "校验通过".green(), packed.len());
+        }
+        std::fs::rename(&part_file, &zip_file).unwrap();
+
+        // 6. 打印压缩文件信息
+        let zip_info = zip_file.metadata().unwrap();
+        println!("压缩文件路径: {}", zip_file.display());
+        println!("压缩文件大小: {}KB", zip_info.len() / 1024);
+        println!("SHA-256: {}", sha256_file(&zip_file).unwrap());
+        Some(zip_file)
+    }
+
+    /// 配置中的加密方式 `[zip].encryption`，默认为 aes128
+    pub fn encryption(filed_map: &Table) -> Encryption {
+        let Some(encryption) = filed_map.get("encryption") else {
+            return Encryption::Aes128;
+        };
+        match encryption.as_str().unwrap().parse() {
+            Ok(encryption) => encryption,
+            Err(err) => {
+                eprintln!("{} {}", "Error [zip].encryption 配置错误:".red(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// 配置中的文件名编码 `[zip].filename_encoding`，默认为 utf8
+    fn filename_encoding(filed_map: &Table) -> FilenameEncoding {
+        let Some(filename_encoding) = filed_map.get("filename_encoding") else {
+            return FilenameEncoding::Utf8;
+        };
+        match filename_encoding.as_str().unwrap().parse() {
+            Ok(filename_encoding) => filename_encoding,
+            Err(err) => {
+                eprintln!(
+                    "{} {}",
+                    "Error [zip].filename_encoding 配置错误:".red(),
+                    err
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// 压缩密码，`--password-prompt` 时从终端读取(不回显)，否则使用配置中的 `password`
+    /// 从终端读取的密码只在本次压缩中使用，不会写入配置文件
+    fn password(filed_map: &Table) -> String {
+        let is_prompt = filed_map
+            .get("password_prompt")
+            .is_some_and(|v| v.as_bool().unwrap());
+        if !is_prompt {
+            return match filed_map.get("password") {
+                Some(password) => password.as_str().unwrap().to_string(),
+                None => String::new(),
+            };
+        }
+        let password = rpassword::prompt_password("请输入压缩密码: ").unwrap();
+        let confirm = rpassword::prompt_password("请再次输入压缩密码: ").unwrap();
+        if password != confirm {
+            eprintln!("{}", "Error 两次输入的密码不一致".red());
+            std::process::exit(1);
+        }
+        password
+    }
+
+    /// 提交清单的文件名，`[zip].manifest` 可选 json toml none，默认为 json
+    fn manifest_name(filed_map: &Table) -> Option<&'static str> {
+        if filed_map
+            .get("no_manifest")
+            .is_some_and(|v| v.as_bool().unwrap())
+        {
+            return None;
+        }
+        match filed_map.get("manifest").map(|v| v.as_str().unwrap()) {
+            None | Some("json") => Some(MANIFEST_JSON),
+            Some("toml") => Some(MANIFEST_TOML),
+            Some("none") => None,
+            Some(other) => {
+                eprintln!(
+                    "{} {}，可选: json toml none",
+                    "Error [zip].manifest 配置错误:".red(),
+                    other
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// 生成提交清单 课程信息从压缩的文件夹或它的上级 `课程序号-课程名称` 文件夹中获取
+    fn build_manifest(
+        dir_path: &Path,
+        packed: &[PackedEntry],
+        config_obj: &Table,
+        archive_options: &ArchiveOptions,
+    ) -> Manifest {
+        let global_filed_map = super::get_global_filed_map(config_obj);
+        let get_global = |key: &str| match global_filed_map.get(key) {
+            Some(value) => value.as_str().unwrap().to_string(),
+            None => String::new(),
+        };
+        let project = find_project(&dir_path.canonicalize().unwrap());
+        let files = packed
+            .iter()
+            .filter(|entry| !entry.is_dir)
+            .map(|entry| ManifestFile {
+                path: entry.name.clone(),
+                size: entry.size,
+                sha256: sha256_file(&entry.source).unwrap(),
+            })
+            .collect();
+        Manifest {
+            user_name: get_global("user_name"),
+            class_name: get_global("class_name"),
+            courses_number: project.as_ref().map(|(number, _)| *number),
+            course_name: project.map(|(_, name)| name),
+            // 可复现的压缩包不记录生成时间
+            created_at: match archive_options.reproducible {
+                true => None,
+                false => Some(chrono::Local::now().to_rfc3339()),
+            },
+            tool: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            files,
+            base: None,
+            unchanged: Vec::new(),
+            commit: None,
+            root: None,
+        }
+    }
+
+    /// 压缩包注释 内容与清单相同，超过 zip 注释的长度限制时不包含文件列表
+    fn manifest_comment(manifest: &Manifest) -> String {
+        let comment = serde_json::to_string(&manifest.to_table()).unwrap();
+        if comment.len() <= u16::MAX as usize {
+            return comment;
+        }
+        let mut table = manifest.to_table();
+        table.remove("files");
+        table.remove("unchanged");
+        serde_json::to_string(&table).unwrap()
+    }
+
+    /// 增量提交时找到上一次提交的压缩包并读取其中的清单，返回 (压缩包名称, 清单)
+    /// `--since-last` 使用输出文件夹中最近生成的提交压缩包，
+    /// `--since` 可以指定压缩包路径，或者日期(使用输出文件夹中这一天生成的压缩包)
+    fn previous_submission(
+        filed_map: &Table,
+        config_obj: &Table,
+        zip_file: &Path,
+        password: &mut Option<String>,
+    ) -> Option<(String, Manifest)> {
+        let since = filed_map.get("since")?.as_str().unwrap();
+        let output_dir = zip_file.parent().unwrap();
+        let archive = match since {
+            SINCE_LAST => Zip::last_archive(output_dir, &get_zip_file_name_prefixes(config_obj)),
+            _ => Zip::since_archive(
+                since,
+                output_dir,
+                config_obj,
+                Zip::project(filed_map).as_ref(),
+            ),
+        };
+        let Some(archive) = archive else {
+            eprintln!(
+                "{} {}",
+                "Error 没有找到上一次提交的压缩包:".red(),
+                match since {
+                    SINCE_LAST => output_dir.display().to_string(),
+                    _ => since.to_string(),
+                }
+            );
+            std::process::exit(1);
+        };
+        let manifest = match Zip::read_submission_manifest(&archive, filed_map, password) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                eprintln!(
+                    "{} {}: {}",
+                    "Error 读取上一次提交的清单失败".red(),
+                    archive.display(),
+                    err
+                );
+                std::process::exit(1);
+            }
+        };
+        println!("增量提交，基准: {}", archive.display());
+        let base = archive.file_name().unwrap().to_string_lossy().to_string();
+        Some((base, manifest))
+    }
+
+    /// 输出文件夹中最近生成的提交压缩包
+    fn last_archive(output_dir: &Path, prefixes: &[String]) -> Option<PathBuf> {
+        std::fs::read_dir(output_dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                prefixes
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix.as_str()))
+                    && (name.ends_with(".zip") || name.ends_with(".7z"))
+            })
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .filter(|(_, path)| path.is_file())
+            .max_by_key(|(modified, _)| *modified)
+            .map(|(_, path)| path)
+    }
+
+    /// `--since` 的值是压缩包路径或者日期
+    /// 日期按照与 [`Zip::output_path`] 相同的名称模板和课程生成压缩包名称
+    fn since_archive(
+        since: &str,
+        output_dir: &Path,
+        config_obj: &Table,
+        project: Option<&(i64, String)>,
+    ) -> Option<PathBuf> {
+        let path = std::env::current_dir().unwrap().join(since);
+        if path.is_file() {
+            return Some(path);
+        }
+        let date = chrono::NaiveDate::parse_from_str(since, "%Y-%m-%d")
+            .or_else(|_| chrono::NaiveDate::parse_from_str(since, "%Y%m%d"))
+            .ok()?;
+        let template = get_name_template(config_obj, "name_template", DEFAULT_NAME_TEMPLATE);
+        let number = project.map(|(number, _)| number.to_string());
+        let project = project.map(|(_, name)| (number.as_deref().unwrap(), name.as_str()));
+        let name = render_zip_file_name(config_obj, &template, date, project);
+        ["zip", "7z"]
+            .iter()
+            .map(|ext| output_dir.join(format!("{}.{}", name, ext)))
+            .find(|path| path.is_file())
+    }
+
+    /// 读取之前提交的压缩包中的清单，zip 注释没有加密 优先从注释中读取
+    fn read_submission_manifest(
+        archive: &Path,
+        filed_map: &Table,
+        password: &mut Option<String>,
+    ) -> Result<Manifest, String> {
+        if let Ok(comment) = read_comment(archive) {
+            if let Ok(manifest) = Manifest::parse(MANIFEST_JSON, comment.as_bytes()) {
+                return Ok(manifest);
+            }
+        }
+        let password = password.get_or_insert_with(|| Zip::password(filed_map));
+        match read_manifest_file(archive, password)? {
+            Some((_, manifest)) => Ok(manifest),
+            None => Err("压缩包中没有提交清单".to_string()),
+        }
+    }
+
+    /// 去掉与上一次提交相同的文件，只保留新增和修改的文件
+    /// 不包含任何保留文件的文件夹也不再放入压缩包
+    fn increment(
+        packed: &mut Vec<PackedEntry>,
+        excluded: &mut Vec<Excluded>,
+        base: String,
+        previous: &Manifest,
+    ) -> Increment {
+        let previous_files = previous
+            .tree()
+            .map(|file| (file.path.as_str(), file))
+            .collect::<BTreeMap<&str, &ManifestFile>>();
+        let deleted = previous_files
+            .keys()
+            .filter(|path| {
+                !packed
+                    .iter()
+                    .any(|entry| !entry.is_dir && entry.name == **path)
+            })
+            .map(|path| path.to_string())
+            .collect();
+        let mut unchanged = Vec::new();
+        packed.retain(|entry| {
+            if entry.is_dir {
+                return true;
+            }
+            let Some(file) = previous_files.get(entry.name.as_str()) else {
+                return true;
+            };
+            if file.size != entry.size {
+                return true;
+            }
+            let sha256 = sha256_file(&entry.source).unwrap();
+            if sha256 != file.sha256 {
+                return true;
+            }
+            unchanged.push(ManifestFile {
+                path: entry.name.clone(),
+                size: entry.size,
+                sha256,
+            });
+            excluded.push(Excluded {
+                name: entry.name.clone(),
+                rule: ExcludeRule::Unchanged,
+            });
+            false
+        });
+        let kept_files = packed
+            .iter()
+            .filter(|entry| !entry.is_dir)
+            .map(|entry| entry.name.clone())
+            .collect::<Vec<String>>();
+        packed.retain(|entry| {
+            !entry.is_dir
+                || kept_files
+                    .iter()
+                    .any(|name| name.starts_with(&format!("{}/", entry.name)))
+        });
+        Increment {
+            base,
+            unchanged,
+            deleted,
+        }
+    }
+
+    /// 压缩的文件夹所在的课程 `(课程序号, 课程名称)`，用于生成压缩包名称
+    fn project(filed_map: &Table) -> Option<(i64, String)> {
+        let dir_path = filed_map.get("dir_path")?.as_str().unwrap();
+        let dir_path = std::env::current_dir()
+            .unwrap()
+            .join(dir_path)
+            .canonicalize()
+            .ok()?;
+        find_project(&dir_path)
+    }
+
+    /// 压缩包的输出路径
+    /// 优先使用 `--output`(文件或文件夹)，其次是配置中的 `output_dir`，最后是当前路径
+    pub fn output_path(filed_map: &Table, config_obj: &Table) -> PathBuf {
+        let current_dir = std::env::current_dir().unwrap();
+        let project = Zip::project(filed_map);
+        let file_name_str = Path::new(&get_default_zip_file_name(config_obj, project.as_ref()))
+            .with_extension(Zip::encryption(filed_map).extension());
+        let output = filed_map.get("output").map(|v| v.as_str().unwrap());
+        let output_dir = filed_map.get("output_dir").map(|v| v.as_str().unwrap());
+        let path = match (output, output_dir) {
+            (Some(output), _) => {
+                let path = current_dir.join(output);
+                if path.is_dir() || output.ends_with('/') || output.ends_with('\\') {
+                    path.join(file_name_str)
+                } else {
+                    path
+                }
+            }
+            (None, Some(output_dir)) if !output_dir.is_empty() => {
+                current_dir.join(output_dir).join(file_name_str)
+            }
+            _ => current_dir.join(file_name_str),
+        };
+        // 去掉路径中的 `.` 和 `..`，便于和遍历到的文件进行比较
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                std::path::Component::CurDir => {}
+                std::path::Component::ParentDir => {
+                    normalized.pop();
+                }
+                _ => normalized.push(component),
+            }
+        }
+        normalized
+    }
+
+    /// `[zip].wrap` 时压缩包中的顶层文件夹名称，与压缩包的文件名(不含扩展名)相同
+    fn wrap_root(filed_map: &Table, zip_file: &Path) -> Option<String> {
+        let is_wrap = filed_map.get("wrap").is_some_and(|v| v.as_bool().unwrap());
+        is_wrap.then(|| zip_file.file_stem().unwrap().to_string_lossy().to_string())
+    }
+
+    /// 额外放入压缩包的文件 (源路径, 压缩包中的路径)
+    /// `--add` 的相对路径相对于当前路径，`[zip.extra_files]` 的相对路径相对于压缩的文件夹
+    fn extra_files(dir_path: &Path, filed_map: &Table) -> Vec<(PathBuf, String)> {
+        let current_dir = std::env::current_dir().unwrap();
+        let mut extra_files = Vec::new();
+        for add in get_string_list(filed_map, "add") {
+            // 不把 Windows 盘符中的 `:` 当作分隔符
+            let (source, name) = match add.rsplit_once(':') {
+                Some((source, name)) if source.len() > 1 && !name.starts_with(['/', '\\']) => {
+                    (source, Some(name))
+                }
+                _ => (add.as_str(), None),
+            };
+            let source = current_dir.join(source);
+            let name = match name {
+                Some(name) => name.to_string(),
+                None => match source.file_name() {
+                    Some(file_name) => file_name.to_string_lossy().to_string(),
+                    None => {
+                        eprintln!("{} {}", "Error --add 需要指定压缩包中的路径:".red(), add);
+                        std::process::exit(1);
+                    }
+                },
+            };
+            extra_files.push((source, name));
+        }
+        if let Some(table) = filed_map.get("extra_files") {
+            for (name, source) in table.as_table().unwrap() {
+                let Some(source) = source.as_str() else {
+                    eprintln!(
+                        "{} {} 的值必须是源文件路径",
+                        "Error [zip.extra_files] 配置错误:".red(),
+                        name
+                    );
+                    std::process::exit(1);
+                };
+                extra_files.push((dir_path.join(source), name.clone()));
+            }
+        }
+        extra_files
+    }
+
+    /// 把文件夹之外的文件按指定的路径加入压缩条目，文件夹会递归加入
+    /// 压缩包中缺少的上级文件夹会一并创建
+    fn add_extra_files(packed: &mut Vec<PackedEntry>, dir_path: &Path, filed_map: &Table) {
+        for (source, name) in Zip::extra_files(dir_path, filed_map) {
+            let name = name.replace('\\', "/").trim_matches('/').to_string();
+            let is_valid = !name.is_empty()
+                && name
+                    .split('/')
+                    .all(|part| !part.is_empty() && part != "." && part != "..");
+            if !is_valid {
+                eprintln!("{} {}", "Error 无效的压缩包路径:".red(), name);
+                std::process::exit(1);
+            }
+            if !source.exists() {
+                eprintln!("{} {}", "Error 额外的文件不存在:".red(), source.display());
+                std::process::exit(1);
+            }
+            let mut entries = Vec::new();
+            let mut parent = String::new();
+            for part in name
+                .split('/')
+                .collect::<Vec<&str>>()
+                .split_last()
+                .unwrap()
+                .1
+            {
+                parent = match parent.is_empty() {
+                    true => part.to_string(),
+                    false => format!("{}/{}", parent, part),
+                };
+                if !packed.iter().any(|entry| entry.name == parent) {
+                    entries.push(PackedEntry {
+                        name: parent.clone(),
+                        source: dir_path.to_owned(),
+                        is_dir: true,
+                        size: 0,
+                    });
+                }
+            }
+            for entry in WalkDir::new(&source).sort_by_file_name() {
+                let entry = entry.unwrap();
+                let relative = entry.path().strip_prefix(&source).unwrap();
+                let is_dir = entry.file_type().is_dir();
+                let entry_name = match relative.as_os_str().is_empty() {
+                    true => name.clone(),
+                    false => format!("{}/{}", name, relative.to_string_lossy().replace('\\', "/")),
+                };
+                if let Some(existing) = packed.iter().find(|entry| entry.name == entry_name) {
+                    // 同名的文件夹可以合并
+                    if is_dir && existing.is_dir {
+                        continue;
+                    }
+                    eprintln!(
+                        "{} {} ({})",
+                        "Error 额外的文件与压缩包中已有的条目重名:".red(),
+                        entry_name,
+                        entry.path().display()
+                    );
+                    std::process::exit(1);
+                }
+                entries.push(PackedEntry {
+                    name: entry_name,
+                    source: entry.path().to_owned(),
+                    is_dir,
+                    size: match is_dir {
+                        true => 0,
+                        false => entry.metadata().unwrap().len(),
+                    },
+                });
+            }
+            packed.extend(entries);
+        }
+    }
+
+    /// 遍历文件夹，得到需要压缩的条目以及被排除的路径和原因
+    /// 被排除的文件夹不会再深入遍历
+    pub fn collect_entries(
+        dir_path: &Path,
+        filed_map: &Table,
+        skip_files: &[&Path],
+        archive_prefixes: &[String],
+    ) -> (Vec<PackedEntry>, Vec<Excluded>) {
+        let ignore_dir = get_string_list(filed_map, "ignore");
+        let patterns = get_string_list(filed_map, "exclude")
+            .iter()
+            .map(|pattern| match Pattern::new(pattern) {
+                Ok(compiled) => compiled,
+                Err(err) => {
+                    eprintln!("{} {} {}", "Error 无效的匹配规则:".red(), pattern, err);
+                    std::process::exit(1);
+                }
+            })
+            .collect::<Vec<Pattern>>();
+        let max_file_size = filed_map
+            .get("max_file_size")
+            .map(|value| match parse_size(value) {
+                Some(size) => size,
+                None => {
+                    eprintln!("{} {}", "Error 无效的 max_file_size:".red(), value);
+                    std::process::exit(1);
+                }
+            });
+
+        // 通过规范化的路径判断是否是本次输出的压缩包 避免 `..` 等写法绕过检查
+        let canonical_dir = dir_path.canonicalize().unwrap();
+        let skip_files = skip_files
+            .iter()
+            .filter_map(|path| {
+                let parent = path.parent()?.canonicalize().ok()?;
+                Some(parent.join(path.file_name()?))
+            })
+            .collect::<Vec<PathBuf>>();
+
+        // 已检测到的项目 (项目文件夹的相对路径, 预设)
+        let enabled_presets = Zip::enabled_presets(filed_map);
+        let mut projects = presets::detect(dir_path, &enabled_presets)
+            .into_iter()
+            .map(|preset| (String::new(), preset))
+            .collect::<Vec<(String, &Preset)>>();
+
+        let mut packed = Vec::new();
+        let mut excluded = Vec::new();
+        // 遍历当前文件夹下所有文件 按文件名排序保证每次压缩的条目顺序一致
+        let mut it = WalkDir::new(dir_path).sort_by_file_name().into_iter();
+        while let Some(entry) = it.next() {
             let entry = entry.unwrap();
             let path = entry.path();
             // 去除前缀
-            let strip_prefix_str = path.strip_prefix(&dir_path).unwrap().to_str().unwrap();
-            if strip_prefix_str == file_name_str {
+            let strip_prefix_str = path.strip_prefix(dir_path).unwrap().to_str().unwrap();
+            // 根目录本身不需要写入
+            if strip_prefix_str.is_empty() {
                 continue;
             }
+            let unix_style_path = strip_prefix_str.replace("\\", "/");
+            let is_dir = entry.file_type().is_dir();
+            let size = if is_dir {
+                0
+            } else {
+                entry.metadata().unwrap().len()
+            };
+            let file_name = entry.file_name().to_string_lossy();
+            let file_name = file_name.trim_start_matches('.');
+            let is_archive = !is_dir
+                && (skip_files.contains(&canonical_dir.join(strip_prefix_str))
+                    || (archive_prefixes
+                        .iter()
+                        .any(|prefix| file_name.starts_with(prefix.as_str()))
+                        && [".zip", ".7z", ".part"]
+                            .iter()
+                            .any(|ext| file_name.ends_with(ext))));
             // 通过路径分隔符分割 判断是否在忽略列表中
-            let path_vec: Vec<&str> = strip_prefix_str.split(std::path::MAIN_SEPARATOR).collect();
-            let mut is_ignore = false;
-            for the_path_str in path_vec {
-                if ignore_dir.contains(&the_path_str.to_string()) {
-                    is_ignore = true;
-                    break;
+            let rule = if is_archive {
+                Some(ExcludeRule::Archive)
+            } else if let Some(name) = unix_style_path
+                .split('/')
+                .find(|the_path_str| ignore_dir.iter().any(|ignore| ignore == the_path_str))
+            {
+                Some(ExcludeRule::Ignore(name.to_string()))
+            } else if let Some(pattern) = patterns.iter().find(|p| p.matches(&unix_style_path)) {
+                Some(ExcludeRule::Pattern(pattern.as_str().to_string()))
+            } else if let Some((name, pattern)) = projects.iter().find_map(|(project, preset)| {
+                let relative = match project.is_empty() {
+                    true => unix_style_path.as_str(),
+                    false => unix_style_path
+                        .strip_prefix(project.as_str())?
+                        .strip_prefix('/')?,
+                };
+                Some((preset.name, preset.matches(relative)?))
+            }) {
+                Some(ExcludeRule::Preset(name, pattern))
+            } else {
+                match max_file_size {
+                    Some(limit) if size > limit => Some(ExcludeRule::SizeLimit(limit)),
+                    _ => None,
+                }
+            };
+            match rule {
+                Some(rule) => {
+                    if is_dir {
+                        it.skip_current_dir();
+                    }
+                    excluded.push(Excluded {
+                        name: unix_style_path,
+                        rule,
+                    });
+                }
+                None => {
+                    if is_dir {
+                        // 子文件夹可能是另一种类型的项目
+                        projects.extend(
+                            presets::detect(path, &enabled_presets)
+                                .into_iter()
+                                .map(|preset| (unix_style_path.clone(), preset)),
+                        );
+                    }
+                    packed.push(PackedEntry {
+                        name: unix_style_path,
+                        source: path.to_owned(),
+                        is_dir,
+                        size,
+                    })
                 }
             }
-            if is_ignore {
+        }
+        (packed, excluded)
+    }
+
+    /// `--git-tracked` 时找到压缩的文件夹所在的仓库，提醒未提交的修改
+    /// 不使用 `--worktree` 时把 HEAD 中的文件导出到临时文件夹
+    fn git_source(dir_path: &Path, filed_map: &Table) -> Option<GitSource> {
+        let get_bool = |key: &str| filed_map.get(key).is_some_and(|v| v.as_bool().unwrap());
+        if !get_bool("git_tracked") {
+            return None;
+        }
+        let is_worktree = get_bool("worktree");
+        let canonical_dir = dir_path.canonicalize().unwrap();
+        let Some(repo) = Repository::discover(&canonical_dir) else {
+            eprintln!(
+                "{} {}",
+                "Error 压缩的文件夹不在 git 仓库中:".red(),
+                dir_path.display()
+            );
+            std::process::exit(1);
+        };
+        let exit_on_error = |err: Box<dyn std::error::Error>| -> ! {
+            eprintln!("{} {}", "Error 读取 git 仓库失败:".red(), err);
+            std::process::exit(1);
+        };
+        let prefix = canonical_dir
+            .strip_prefix(repo.work_dir.canonicalize().unwrap())
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+        let head = repo.head().unwrap_or_else(|err| exit_on_error(err));
+        if head.is_none() && !is_worktree {
+            eprintln!("{}", "Error git 仓库中还没有提交".red());
+            std::process::exit(1);
+        }
+        let changes = repo
+            .changes(&prefix)
+            .unwrap_or_else(|err| exit_on_error(err));
+        // 输出到 stderr 避免影响 `--format json` 的输出
+        if !changes.is_empty() {
+            let message = match is_worktree {
+                true => "压缩的内容包含以下未提交的修改:",
+                false => "以下未提交的修改不会被压缩，压缩的是 HEAD 提交中的内容:",
+            };
+            eprintln!("{} {}", "Warning".yellow(), message);
+            for change in &changes {
+                eprintln!("  {}", change.to_string().yellow());
+            }
+        }
+        let export = match is_worktree {
+            true => None,
+            false => {
+                let export = TempDir::new("git").unwrap();
+                repo.export(head.as_ref().unwrap(), &prefix, &export.0)
+                    .unwrap_or_else(|err| exit_on_error(err));
+                Some(export)
+            }
+        };
+        let commit = head.map(|head| match is_worktree && !changes.is_empty() {
+            true => format!("{}-dirty", head),
+            false => head,
+        });
+        Some(GitSource {
+            repo,
+            prefix,
+            commit,
+            export,
+        })
+    }
+
+    /// `--git-tracked --worktree` 时排除暂存区中没有的文件，以及不包含被跟踪的文件的文件夹
+    fn retain_tracked(
+        git: &GitSource,
+        packed: &mut Vec<PackedEntry>,
+        excluded: &mut Vec<Excluded>,
+    ) {
+        let index = match git.repo.index() {
+            Ok(index) => index,
+            Err(err) => {
+                eprintln!("{} {}", "Error 读取 git 仓库失败:".red(), err);
+                std::process::exit(1);
+            }
+        };
+        let tracked = index
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect::<BTreeSet<String>>();
+        let repo_path = |name: &str| match git.prefix.is_empty() {
+            true => name.to_string(),
+            false => format!("{}/{}", git.prefix, name),
+        };
+        let (files, untracked): (Vec<PackedEntry>, Vec<PackedEntry>) = std::mem::take(packed)
+            .into_iter()
+            .partition(|entry| entry.is_dir || tracked.contains(&repo_path(&entry.name)));
+        let (kept, empty_dirs): (Vec<PackedEntry>, Vec<PackedEntry>) =
+            files.into_iter().partition(|entry| {
+                !entry.is_dir
+                    || tracked
+                        .iter()
+                        .any(|path| path.starts_with(&format!("{}/", repo_path(&entry.name))))
+            });
+        *packed = kept;
+        // 与遍历时一样 被排除的文件夹中的内容不再单独列出
+        let empty_dirs = empty_dirs
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<String>>();
+        let in_empty_dir = |name: &str| {
+            empty_dirs
+                .iter()
+                .any(|dir| name.starts_with(&format!("{}/", dir)))
+        };
+        let mut untracked = untracked
+            .into_iter()
+            .map(|entry| entry.name)
+            .chain(empty_dirs.iter().cloned())
+            .filter(|name| !in_empty_dir(name))
+            .map(|name| Excluded {
+                name,
+                rule: ExcludeRule::Untracked,
+            })
+            .collect::<Vec<Excluded>>();
+        untracked.sort_by(|a, b| a.name.cmp(&b.name));
+        excluded.extend(untracked);
+    }
+
+    /// 压缩前检查将要压缩的文件，返回需要提醒的文件
+    fn guard_check(packed: &[PackedEntry], filed_map: &Table) -> Vec<(String, GuardIssue)> {
+        let rules = Zip::guard_rules(filed_map);
+        if !rules.enabled {
+            return Vec::new();
+        }
+        let mut warnings: Vec<(String, GuardIssue)> = Vec::new();
+        for entry in packed {
+            // 已经提醒过的文件夹中的内容不再重复提醒
+            let in_flagged_dir = warnings
+                .iter()
+                .any(|(name, _)| entry.name.starts_with(&format!("{}/", name)));
+            if in_flagged_dir {
                 continue;
             }
-            let unix_style_path = strip_prefix_str.replace("\\", "/");
-            if path.is_dir() {
-                zip_writer.add_directory(unix_style_path, options).unwrap();
-            }else{
-                zip_writer.start_file_from_path(unix_style_path, options).unwrap();
-                let mut file = std::fs::File::open(path).unwrap();
-                file.read_to_end(&mut buf).unwrap();
-                zip_writer.write_all(&buf).unwrap();
-                buf.clear();
+            if let Some(issue) = rules.check(&entry.source, entry.is_dir, entry.size) {
+                warnings.push((entry.name.clone(), issue));
             }
         }
-        zip_writer.finish().unwrap();
+        warnings
+    }
 
-        // 6. 打印压缩文件信息
-        let zip_file = current_dir.join(&file_name_str);
-        let zip_info = zip_file.metadata().unwrap();
-        println!("压缩文件路径: {}", zip_file.display());
-        println!("压缩文件大小: {}KB", zip_info.len() / 1024);
+    /// `--level`/`[zip].level` 以及 `[zip.compression]` 中的压缩规则
+    fn compression_rules(filed_map: &Table) -> CompressionRules {
+        let table = filed_map.get("compression").map(|v| v.as_table().unwrap());
+        let level = filed_map.get("level").map(|v| v.as_integer().unwrap());
+        match CompressionRules::from_table(table, level) {
+            Ok(rules) => rules,
+            Err(err) => {
+                eprintln!(
+                    "{} {}",
+                    "Error [zip].level 或 [zip.compression] 配置错误:".red(),
+                    err
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn guard_rules(filed_map: &Table) -> GuardRules {
+        let table = filed_map.get("guard").map(|v| v.as_table().unwrap());
+        match GuardRules::from_table(table) {
+            Ok(rules) => rules,
+            Err(err) => {
+                eprintln!("{} {}", "Error [zip.guard] 配置错误:".red(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// 打印压缩前检查的结果
+    /// `--strict` 时存在问题直接终止，`--interactive` 时逐个询问是否排除
+    fn guard(
+        packed: &mut Vec<PackedEntry>,
+        excluded: &mut Vec<Excluded>,
+        warnings: Vec<(String, GuardIssue)>,
+        filed_map: &Table,
+    ) {
+        if warnings.is_empty() {
+            return;
+        }
+        let get_bool = |key: &str| filed_map.get(key).is_some_and(|v| v.as_bool().unwrap());
+        let is_strict = get_bool("strict") || Zip::guard_rules(filed_map).strict;
+        let is_interactive = get_bool("interactive");
+        for (name, issue) in &warnings {
+            println!("{} {} ({})", "Warning".yellow(), name.yellow(), issue);
+        }
+        if is_strict {
+            eprintln!(
+                "{} 存在 {} 个需要注意的文件，已终止压缩",
+                "Error".red(),
+                warnings.len()
+            );
+            std::process::exit(1);
+        }
+        if !is_interactive {
+            return;
+        }
+        let stdin = std::io::stdin();
+        for (name, issue) in warnings {
+            print!("排除 {} ({})? [y/N] ", name.yellow(), issue);
+            std::io::stdout().flush().unwrap();
+            let mut answer = String::new();
+            stdin.read_line(&mut answer).unwrap();
+            if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                continue;
+            }
+            let dir_prefix = format!("{}/", name);
+            packed.retain(|entry| entry.name != name && !entry.name.starts_with(&dir_prefix));
+            excluded.push(Excluded {
+                name,
+                rule: ExcludeRule::Guard(issue.to_string()),
+            });
+        }
+    }
+
+    /// 启用的构建产物预设
+    /// 可以通过 `--no-presets` 或 `[zip.presets]` 中的 `enabled = false` 全部关闭，
+    /// 也可以通过 `预设名称 = false` 单独关闭某个预设
+    fn enabled_presets(filed_map: &Table) -> Vec<&'static Preset> {
+        if let Some(true) = filed_map.get("no_presets").map(|v| v.as_bool().unwrap()) {
+            return Vec::new();
+        }
+        let Some(table) = filed_map.get("presets") else {
+            return PRESETS.iter().collect();
+        };
+        let table = table.as_table().unwrap();
+        for key in table.keys() {
+            if key != "enabled" && Preset::by_name(key).is_none() {
+                eprintln!("{} [zip.presets] 中的 {}", "Error 未知的预设:".red(), key);
+                std::process::exit(1);
+            }
+        }
+        if let Some(false) = table.get("enabled").map(|v| v.as_bool().unwrap()) {
+            return Vec::new();
+        }
+        PRESETS
+            .iter()
+            .filter(|preset| {
+                table
+                    .get(preset.name)
+                    .is_none_or(|enabled| enabled.as_bool().unwrap())
+            })
+            .collect()
+    }
+
+    /// 以树形结构打印将要压缩的文件，以及被排除的路径
+    fn print_dry_run(
+        dir_path: &Path,
+        zip_file: &Path,
+        packed: &[PackedEntry],
+        excluded: &[Excluded],
+        warnings: &[(String, GuardIssue)],
+        increment: Option<&Increment>,
+    ) {
+        println!("{} {}", "压缩文件夹:".blue(), dir_path.display());
+        println!("{} {}", "压缩文件路径:".blue(), zip_file.display());
+        println!("{}", "将要压缩的文件:".blue());
+        let mut total_size = 0;
+        let mut file_count = 0;
+        for entry in packed {
+            let depth = entry.name.matches('/').count();
+            let name = entry.name.rsplit('/').next().unwrap();
+            if entry.is_dir {
+                println!("{}{}/", "  ".repeat(depth + 1), name);
+            } else {
+                println!(
+                    "{}{} ({})",
+                    "  ".repeat(depth + 1),
+                    name,
+                    format_size(entry.size)
+                );
+                total_size += entry.size;
+                file_count += 1;
+            }
+        }
+        println!(
+            "共 {} 个文件, 总大小: {}",
+            file_count.to_string().green(),
+            format_size(total_size).green()
+        );
+        if !excluded.is_empty() {
+            println!("{}", "被排除的路径:".yellow());
+            for item in excluded {
+                println!("  {} ({})", item.name.yellow(), item.rule);
+            }
+        }
+        if !warnings.is_empty() {
+            println!("{}", "需要注意的文件:".yellow());
+            for (name, issue) in warnings {
+                println!("  {} ({})", name.yellow(), issue);
+            }
+        }
+        if let Some(increment) = increment {
+            if !increment.deleted.is_empty() {
+                println!("{}", "已删除的文件(记录在 DELETED.txt 中):".yellow());
+                for name in &increment.deleted {
+                    println!("  {}", name.yellow());
+                }
+            }
+        }
+    }
+
+    /// json格式的 `--dry-run` 结果，方便脚本检查
+    fn dry_run_json(
+        dir_path: &Path,
+        zip_file: &Path,
+        packed: &[PackedEntry],
+        excluded: &[Excluded],
+        warnings: &[(String, GuardIssue)],
+        increment: Option<&Increment>,
+    ) -> serde_json::Value {
+        let files = packed.iter().filter(|entry| !entry.is_dir);
+        let mut output = serde_json::json!({
+            "dir_path": dir_path.display().to_string(),
+            "archive": zip_file.display().to_string(),
+            "entries": packed
+                .iter()
+                .map(|entry| serde_json::json!({
+                    "path": entry.name,
+                    "is_dir": entry.is_dir,
+                    "size": entry.size,
+                }))
+                .collect::<Vec<_>>(),
+            "file_count": files.clone().count(),
+            "total_size": files.map(|entry| entry.size).sum::<u64>(),
+            "excluded": excluded
+                .iter()
+                .map(|item| serde_json::json!({
+                    "path": item.name,
+                    "rule": item.rule.kind(),
+                    "detail": item.rule.detail(),
+                }))
+                .collect::<Vec<_>>(),
+            "warnings": warnings
+                .iter()
+                .map(|(name, issue)| serde_json::json!({
+                    "path": name,
+                    "reason": issue.to_string(),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(increment) = increment {
+            output["base"] = serde_json::json!(increment.base);
+            output["deleted"] = serde_json::json!(increment.deleted);
+        }
+        output
+    }
+
+    /// 重新打开压缩包，逐个解压(如有密码则解密)校验CRC，
+    /// 并与源文件的列表和大小进行对比，返回所有发现的问题
+    pub fn verify(
+        zip_path: &Path,
+        password: &str,
+        packed: &[PackedEntry],
+        generated: &[&str],
+        root: Option<&str>,
+    ) -> Vec<String> {
+        let mut problems = Vec::new();
+        let entries = match read_entries(zip_path, password) {
+            Ok(entries) => entries,
+            Err(err) => {
+                problems.push(format!("无法读取压缩包 {}: {}", zip_path.display(), err));
+                return problems;
+            }
+        };
+        // 期望的条目 key为压缩包路径
+        let mut expected: BTreeMap<&str, &PackedEntry> = packed
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry))
+            .collect();
+        for info in entries {
+            let name = match root {
+                Some(root) if info.name == root && info.is_dir => continue,
+                Some(root) => match info.name.strip_prefix(&format!("{}/", root)) {
+                    Some(name) => name.to_string(),
+                    None => {
+                        problems.push(format!("{}: 不在顶层文件夹 {} 中", info.name, root));
+                        continue;
+                    }
+                },
+                None => info.name,
+            };
+            if let Some(err) = info.error {
+                problems.push(format!("{}: {}", name, err));
+                expected.remove(name.as_str());
+                continue;
+            }
+            if generated.contains(&name.as_str()) {
+                continue;
+            }
+            let Some(entry) = expected.remove(name.as_str()) else {
+                problems.push(format!("{}: 源文件夹中不存在此条目", name));
+                continue;
+            };
+            if entry.is_dir != info.is_dir {
+                problems.push(format!("{}: 条目类型与源文件不一致", name));
+                continue;
+            }
+            if entry.is_dir {
+                continue;
+            }
+            match entry.source.metadata() {
+                Ok(metadata) if metadata.len() != info.size => problems.push(format!(
+                    "{}: 大小不一致 源文件 {} 字节, 压缩包 {} 字节",
+                    name,
+                    metadata.len(),
+                    info.size
+                )),
+                Ok(_) => {}
+                Err(err) => problems.push(format!("{}: 无法读取源文件 {}", name, err)),
+            }
+        }
+        for name in expected.keys() {
+            problems.push(format!("{}: 压缩包中缺少此条目", name));
+        }
+        problems
     }
 
     fn zip(&self, filed_map: &Table, config_obj: &Table) {
-        let dir_path_str = filed_map.get("dir_path").unwrap().as_str().unwrap();
-        let mut ignore_dir = filed_map
-            .get("ignore")
-            .unwrap()
+        let is_batch = ["course", "range", "all_unsubmitted"]
+            .iter()
+            .any(|key| filed_map.contains_key(*key));
+        if is_batch {
+            Zip::batch(filed_map, config_obj);
+        } else {
+            Zip::_zip(filed_map, config_obj);
+        }
+    }
+
+    /// 批量压缩工作目录(`[new].workspace`)中的多个课程
+    /// 默认每个课程按 `course_name_template` 生成一个压缩包，`--combined` 时生成一个压缩包
+    fn batch(filed_map: &Table, config_obj: &Table) {
+        let workspace = config_obj
+            .get("new")
+            .and_then(|new| new.as_table()?.get("workspace")?.as_str())
+            .unwrap_or_default();
+        let projects = list_projects(Path::new(workspace));
+        if projects.is_empty() {
+            eprintln!("{} {}", "Error 工作目录中没有课程文件夹:".red(), workspace);
+            std::process::exit(1);
+        }
+        let output_file = Zip::output_path(filed_map, config_obj);
+        let output_dir = output_file.parent().unwrap();
+        let selected = Zip::select_courses(filed_map, config_obj, &projects, output_dir);
+        if selected.is_empty() {
+            println!("{}", "没有需要压缩的课程".yellow());
+            return;
+        }
+        let extension = Zip::encryption(filed_map).extension();
+        let today = chrono::Local::now().date_naive();
+        if filed_map
+            .get("combined")
+            .is_some_and(|v| v.as_bool().unwrap())
+        {
+            let numbers = selected
+                .iter()
+                .map(|(number, _, _)| number.to_string())
+                .collect::<Vec<String>>()
+                .join("_");
+            let names = selected
+                .iter()
+                .map(|(_, name, _)| name.as_str())
+                .collect::<Vec<&str>>()
+                .join("_");
+            let template = get_name_template(config_obj, "name_template", DEFAULT_NAME_TEMPLATE);
+            let name = render_zip_file_name(config_obj, &template, today, Some((&numbers, &names)));
+            let mut course_filed_map = filed_map.clone();
+            course_filed_map.insert("dir_path".to_string(), toml::Value::from(workspace));
+            course_filed_map.insert(
+                "output".to_string(),
+                toml::Value::from(
+                    output_dir
+                        .join(format!("{}.{}", name, extension))
+                        .to_str()
+                        .unwrap(),
+                ),
+            );
+            course_filed_map.insert(
+                "course_dirs".to_string(),
+                toml::Value::Array(
+                    selected
+                        .iter()
+                        .map(|(_, _, path)| {
+                            toml::Value::from(path.file_name().unwrap().to_str().unwrap())
+                        })
+                        .collect(),
+                ),
+            );
+            Zip::_zip(&course_filed_map, config_obj);
+            return;
+        }
+        let template = get_name_template(
+            config_obj,
+            "course_name_template",
+            DEFAULT_COURSE_NAME_TEMPLATE,
+        );
+        for (number, name, path) in &selected {
+            println!("{} {}-{}", "压缩课程:".blue(), number, name);
+            let name = render_zip_file_name(
+                config_obj,
+                &template,
+                today,
+                Some((&number.to_string(), name)),
+            );
+            let mut course_filed_map = filed_map.clone();
+            course_filed_map.insert(
+                "dir_path".to_string(),
+                toml::Value::from(path.to_str().unwrap()),
+            );
+            course_filed_map.insert(
+                "output".to_string(),
+                toml::Value::from(
+                    output_dir
+                        .join(format!("{}.{}", name, extension))
+                        .to_str()
+                        .unwrap(),
+                ),
+            );
+            Zip::_zip(&course_filed_map, config_obj);
+        }
+    }
+
+    /// 根据 `--course` `--range` `--all-unsubmitted` 选出需要压缩的课程
+    fn select_courses(
+        filed_map: &Table,
+        config_obj: &Table,
+        projects: &[(i64, String, PathBuf)],
+        output_dir: &Path,
+    ) -> Vec<(i64, String, PathBuf)> {
+        let courses = match filed_map.get("course") {
+            Some(courses) => courses
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v.as_integer().unwrap())
+                .collect(),
+            None => Vec::new(),
+        };
+        for number in &courses {
+            if !projects.iter().any(|(n, _, _)| n == number) {
+                eprintln!("{} {}", "Error 工作目录中没有这个课程:".red(), number);
+                std::process::exit(1);
+            }
+        }
+        let range = filed_map.get("range").map(|range| {
+            let range = range.as_str().unwrap();
+            let parsed = range.split_once("..").and_then(|(start, end)| {
+                let start = start.trim().parse::<i64>().ok()?;
+                let end = end.trim().trim_start_matches('=').parse::<i64>().ok()?;
+                Some(start..=end)
+            });
+            match parsed {
+                Some(parsed) if !parsed.is_empty() => parsed,
+                _ => {
+                    eprintln!("{} {}，例如: 10..14", "Error 无效的课程范围:".red(), range);
+                    std::process::exit(1);
+                }
+            }
+        });
+        let submitted = match filed_map.get("all_unsubmitted") {
+            Some(_) => Zip::submitted_courses(filed_map, config_obj, output_dir),
+            None => BTreeSet::new(),
+        };
+        let is_all_unsubmitted = filed_map.contains_key("all_unsubmitted");
+        projects
+            .iter()
+            .filter(|(number, _, _)| {
+                courses.contains(number)
+                    || range.as_ref().is_some_and(|range| range.contains(number))
+                    || (is_all_unsubmitted && !submitted.contains(number))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// 输出文件夹中已经提交过的课程序号，根据之前生成的压缩包中的清单判断
+    fn submitted_courses(
+        filed_map: &Table,
+        config_obj: &Table,
+        output_dir: &Path,
+    ) -> BTreeSet<i64> {
+        let prefixes = get_zip_file_name_prefixes(config_obj);
+        let mut submitted = BTreeSet::new();
+        let Ok(read_dir) = std::fs::read_dir(output_dir) else {
+            return submitted;
+        };
+        let mut password = None;
+        for entry in read_dir.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_archive = prefixes
+                .iter()
+                .any(|prefix| name.starts_with(prefix.as_str()))
+                && (name.ends_with(".zip") || name.ends_with(".7z"));
+            if !is_archive {
+                continue;
+            }
+            let Ok(manifest) =
+                Zip::read_submission_manifest(&entry.path(), filed_map, &mut password)
+            else {
+                continue;
+            };
+            submitted.extend(manifest.courses_number);
+            // 合并提交的压缩包中每个课程是一个文件夹
+            submitted.extend(manifest.tree().filter_map(|file| {
+                parse_project_name(file.path.split('/').next()?).map(|(number, _)| number)
+            }));
+        }
+        submitted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(zip: &str) -> Table {
+        format!(
+            "[global]\nuser_name = \"张三\"\nclass_name = \"CR49\"\n[zip]\n{}",
+            zip
+        )
+        .parse::<Table>()
+        .unwrap()
+    }
+
+    /// 在 `root` 中创建文件，`files` 为 `(相对路径, 内容)`
+    fn write_files(root: &Path, files: &[(&str, &str)]) {
+        for (name, content) in files {
+            let path = root.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    /// 由配置和额外的字段合成压缩 `dir_path` 到 `output` 的字段
+    fn zip_filed_map(config_obj: &Table, dir_path: &Path, output: &Path, fields: &str) -> Table {
+        let mut filed_map = config_filed_map(config_obj);
+        filed_map.extend(fields.parse::<Table>().unwrap());
+        filed_map.insert("dir_path".to_string(), dir_path.to_str().unwrap().into());
+        filed_map.insert("output".to_string(), output.to_str().unwrap().into());
+        filed_map
+    }
+
+    /// 使用配置和额外的字段压缩 `dir_path`，返回生成的压缩包
+    fn zip_dir(config_obj: &Table, dir_path: &Path, output: &Path, fields: &str) -> PathBuf {
+        let filed_map = zip_filed_map(config_obj, dir_path, output, fields);
+        Zip::_zip(&filed_map, config_obj).unwrap()
+    }
+
+    #[test]
+    fn reproducible_archive() {
+        let config_obj = config("");
+        let temp = TempDir::new("zip-reproducible").unwrap();
+        let dir_path = temp.0.join("13-虚函数");
+        write_files(
+            &dir_path,
+            &[
+                ("main.cpp", "int main() {}\n"),
+                ("笔记.md", "# 虚函数\n"),
+                ("imgs/a.png", "png"),
+            ],
+        );
+        let first = zip_dir(
+            &config_obj,
+            &dir_path,
+            &temp.0.join("1.zip"),
+            "reproducible = true",
+        );
+
+        // 修改时间不同 内容相同时生成的压缩包逐字节一致
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        for entry in WalkDir::new(&dir_path) {
+            let entry = entry.unwrap();
+            std::fs::File::open(entry.path())
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        let second = zip_dir(
+            &config_obj,
+            &dir_path,
+            &temp.0.join("2.zip"),
+            "reproducible = true",
+        );
+        assert_eq!(sha256_file(&first).unwrap(), sha256_file(&second).unwrap());
+        assert_eq!(
+            std::fs::read(&first).unwrap(),
+            std::fs::read(&second).unwrap()
+        );
+    }
+
+    #[test]
+    fn dry_run_lists_entries() {
+        let config_obj = config("exclude = [\"*.log\"]");
+        let temp = TempDir::new("zip-dry-run").unwrap();
+        let dir_path = temp.0.join("13-虚函数");
+        write_files(
+            &dir_path,
+            &[
+                ("main.cpp", "int main() {}\n"),
+                ("imgs/a.png", "png"),
+                ("build.log", "log"),
+            ],
+        );
+        let output = temp.0.join("out.zip");
+        let filed_map = zip_filed_map(&config_obj, &dir_path, &output, "dry_run = true");
+        assert_eq!(Zip::_zip(&filed_map, &config_obj), None);
+        assert!(!output.exists());
+
+        let (packed, excluded) = Zip::collect_entries(&dir_path, &filed_map, &[], &[]);
+        let json = Zip::dry_run_json(&dir_path, &output, &packed, &excluded, &[], None);
+        let mut paths = json["entries"]
             .as_array()
             .unwrap()
             .iter()
-            .map(|v| v.as_str().unwrap().to_string())
+            .map(|entry| entry["path"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+        paths.sort();
+        assert_eq!(paths, vec!["imgs", "imgs/a.png", "main.cpp"]);
+        assert_eq!(json["file_count"], 2);
+        assert_eq!(json["total_size"], 17);
+        assert_eq!(
+            json["excluded"],
+            serde_json::json!([{ "path": "build.log", "rule": "pattern", "detail": "*.log" }])
+        );
+        assert_eq!(json["archive"], output.display().to_string());
+        assert!(json.get("base").is_none());
+    }
+
+    #[test]
+    fn earlier_archives_are_excluded() {
+        let config_obj = config("");
+        let temp = TempDir::new("zip-archives").unwrap();
+        let dir_path = temp.0.join("13-虚函数");
+        write_files(
+            &dir_path,
+            &[
+                ("main.cpp", "int main() {}\n"),
+                ("资料.zip", "zip"),
+                ("CR49_张三_20240101.zip", "zip"),
+                ("CR49_张三_13-虚函数_20240102.7z", "7z"),
+            ],
+        );
+        let filed_map = zip_filed_map(&config_obj, &dir_path, &dir_path.join(""), "");
+        let prefixes = get_zip_file_name_prefixes(&config_obj);
+        let (packed, excluded) = Zip::collect_entries(&dir_path, &filed_map, &[], &prefixes);
+        let mut names = packed
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect::<Vec<&str>>();
+        names.sort();
+        assert_eq!(names, vec!["main.cpp", "资料.zip"]);
+        let mut archives = excluded
+            .iter()
+            .filter(|item| matches!(item.rule, ExcludeRule::Archive))
+            .map(|item| item.name.as_str())
+            .collect::<Vec<&str>>();
+        archives.sort();
+        assert_eq!(
+            archives,
+            vec!["CR49_张三_13-虚函数_20240102.7z", "CR49_张三_20240101.zip"]
+        );
+
+        // 输出到压缩的文件夹中 再次压缩时不包含上一次生成的压缩包
+        zip_dir(&config_obj, &dir_path, &dir_path.join(""), "");
+        let archive = zip_dir(&config_obj, &dir_path, &dir_path.join(""), "");
+        let mut names = read_entries(&archive, "")
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(names, vec![MANIFEST_JSON, "main.cpp", "资料.zip"]);
+    }
+
+    #[test]
+    fn add_extra_files_and_wrap() {
+        let config_obj = config("");
+        let temp = TempDir::new("zip-extra").unwrap();
+        let dir_path = temp.0.join("13-虚函数");
+        write_files(
+            &temp.0,
+            &[
+                ("13-虚函数/main.cpp", "int main() {}\n"),
+                ("笔记.md", "# 虚函数\n"),
+                ("报告/实验一.pdf", "pdf"),
+                ("说明.txt", "readme"),
+            ],
+        );
+        // `--add` 的相对路径相对于当前路径 这里使用绝对路径
+        // `[zip.extra_files]` 的相对路径相对于压缩的文件夹
+        let fields = format!(
+            r#"
+            wrap = true
+            add = ["{}", "{}:docs/报告"]
+            [extra_files]
+            "docs/说明.txt" = "../说明.txt"
+            "#,
+            temp.0.join("笔记.md").display(),
+            temp.0.join("报告").display()
+        );
+        let archive = zip_dir(&config_obj, &dir_path, &temp.0.join("out.zip"), &fields);
+        let mut names = read_entries(&archive, "")
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
             .collect::<Vec<String>>();
-        let file_name_str = get_default_zip_file_name(config_obj);
-        Zip::_zip(dir_path_str, &mut ignore_dir, &file_name_str, &config_obj)
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "out",
+                "out/MANIFEST.json",
+                "out/docs",
+                "out/docs/报告",
+                "out/docs/报告/实验一.pdf",
+                "out/docs/说明.txt",
+                "out/main.cpp",
+                "out/笔记.md",
+            ]
+        );
+    }
+
+    #[test]
+    fn select_courses_for_batch() {
+        let config_obj = config("");
+        let temp = TempDir::new("zip-batch").unwrap();
+        let workspace = temp.0.join("workspace");
+        write_files(
+            &workspace,
+            &[
+                ("12-继承/main.cpp", ""),
+                ("13-虚函数/main.cpp", ""),
+                ("14-纯虚函数/main.cpp", ""),
+                ("15-模板/main.cpp", ""),
+            ],
+        );
+        let projects = list_projects(&workspace);
+        // 课程 14 已经按 course_name_template 生成过压缩包
+        let output_dir = temp.0.join("out");
+        let template = get_name_template(
+            &config_obj,
+            "course_name_template",
+            DEFAULT_COURSE_NAME_TEMPLATE,
+        );
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 5, 20).unwrap();
+        let name = render_zip_file_name(&config_obj, &template, date, Some(("14", "纯虚函数")));
+        zip_dir(
+            &config_obj,
+            &workspace.join("14-纯虚函数"),
+            &output_dir.join(format!("{}.zip", name)),
+            "",
+        );
+
+        let select = |fields: &str| {
+            let filed_map = fields.parse::<Table>().unwrap();
+            Zip::select_courses(&filed_map, &config_obj, &projects, &output_dir)
+                .into_iter()
+                .map(|(number, _, _)| number)
+                .collect::<Vec<i64>>()
+        };
+        assert_eq!(select("course = [15, 12]"), vec![12, 15]);
+        assert_eq!(select("range = \"13..=14\""), vec![13, 14]);
+        assert_eq!(
+            select("course = [12]\nrange = \"14..15\""),
+            vec![12, 14, 15]
+        );
+        assert_eq!(select("all_unsubmitted = true"), vec![12, 13, 15]);
+    }
+
+    #[test]
+    fn since_date_uses_course_in_template() {
+        let config_obj = config("name_template = \"{class}_{user}_{number}-{course}_{date}\"");
+        let temp = TempDir::new("zip-since").unwrap();
+        let archive = temp.0.join("CR49_张三_13-虚函数_20240520.zip");
+        std::fs::write(&archive, b"").unwrap();
+
+        let project = (13, "虚函数".to_string());
+        assert_eq!(
+            Zip::since_archive("2024-05-20", &temp.0, &config_obj, Some(&project)),
+            Some(archive)
+        );
+        assert_eq!(
+            Zip::since_archive("20240520", &temp.0, &config_obj, None),
+            None
+        );
     }
 }
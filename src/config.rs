@@ -1,4 +1,4 @@
-use std::{error::Error, fs, io::Read};
+use std::{error::Error, fs, io::Read, path::PathBuf};
 use toml::Table;
 
 const CONFIG_FILE: &str = "config.toml";
@@ -27,10 +27,138 @@ pub fn update_config_file(table: &Table) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-pub fn get_default_zip_file_name(config_obj: &Table) -> String {
+/// 配置文件所在的文件夹，即程序所在的文件夹，配置中的相对路径相对于这个文件夹
+pub fn config_dir() -> PathBuf {
+    std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+/// 压缩包名称模板 `[zip].name_template` 的默认值
+/// 可用的占位符: `{class}` `{user}` `{date}` `{number}` `{course}`
+pub const DEFAULT_NAME_TEMPLATE: &str = "{class}_{user}_{date}";
+/// 批量压缩时每个课程一个压缩包的名称模板 `[zip].course_name_template` 的默认值
+pub const DEFAULT_COURSE_NAME_TEMPLATE: &str = "{class}_{user}_{number}-{course}_{date}";
+
+/// 读取 `[zip]` 中的名称模板
+pub fn get_name_template(config_obj: &Table, key: &str, default: &str) -> String {
+    config_obj
+        .get("zip")
+        .and_then(|zip| zip.as_table()?.get(key)?.as_str())
+        .unwrap_or(default)
+        .to_string()
+}
+
+/// 根据名称模板生成压缩包名称(不含扩展名)，`project` 为课程序号和课程名称
+pub fn render_zip_file_name(
+    config_obj: &Table,
+    template: &str,
+    date: chrono::NaiveDate,
+    project: Option<(&str, &str)>,
+) -> String {
     let global_table = config_obj.get("global").unwrap().as_table().unwrap();
     let user_name = global_table.get("user_name").unwrap().as_str().unwrap();
     let class_name = global_table.get("class_name").unwrap().as_str().unwrap();
-    let time_str = chrono::Local::now().format("%Y%m%d").to_string();
-    format!("{}_{}_{}.zip", class_name, user_name, time_str)
+    let (number, course) = project.unwrap_or_default();
+    template
+        .replace("{class}", class_name)
+        .replace("{user}", user_name)
+        .replace("{date}", &date.format("%Y%m%d").to_string())
+        .replace("{number}", number)
+        .replace("{course}", course)
+}
+
+/// 默认的压缩包名称，`project` 为压缩的文件夹所在的课程
+pub fn get_default_zip_file_name(config_obj: &Table, project: Option<&(i64, String)>) -> String {
+    let template = get_name_template(config_obj, "name_template", DEFAULT_NAME_TEMPLATE);
+    let number = project.map(|(number, _)| number.to_string());
+    let project = project.map(|(_, name)| (number.as_deref().unwrap(), name.as_str()));
+    let today = chrono::Local::now().date_naive();
+    format!(
+        "{}.zip",
+        render_zip_file_name(config_obj, &template, today, project)
+    )
+}
+
+/// 生成的压缩包名称的固定前缀(默认为 `班级_姓名_`)，用于识别之前生成的压缩包
+/// 即名称模板中第一个 `{class}` `{user}` 以外的占位符之前的部分
+/// `name_template` 和批量压缩的 `course_name_template` 各有一个前缀，相同时只保留一个
+/// 模板以其他占位符开头时没有固定前缀，无法识别按这个模板生成的压缩包
+pub fn get_zip_file_name_prefixes(config_obj: &Table) -> Vec<String> {
+    let today = chrono::Local::now().date_naive();
+    let mut prefixes = Vec::new();
+    for (key, default) in [
+        ("name_template", DEFAULT_NAME_TEMPLATE),
+        ("course_name_template", DEFAULT_COURSE_NAME_TEMPLATE),
+    ] {
+        let template = get_name_template(config_obj, key, default);
+        let mut end = template.len();
+        for placeholder in ["{date}", "{number}", "{course}"] {
+            if let Some(index) = template.find(placeholder) {
+                end = end.min(index);
+            }
+        }
+        let prefix = render_zip_file_name(config_obj, &template[..end], today, None);
+        if !prefix.is_empty() && !prefixes.contains(&prefix) {
+            prefixes.push(prefix);
+        }
+    }
+    prefixes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(zip: &str) -> Table {
+        format!(
+            "[global]\nuser_name = \"张三\"\nclass_name = \"CR49\"\n[zip]\n{}",
+            zip
+        )
+        .parse::<Table>()
+        .unwrap()
+    }
+
+    #[test]
+    fn render_names_and_prefixes() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 5, 20).unwrap();
+        let config_obj = config("");
+        assert_eq!(
+            render_zip_file_name(&config_obj, DEFAULT_NAME_TEMPLATE, date, None),
+            "CR49_张三_20240520"
+        );
+        let template = get_name_template(
+            &config_obj,
+            "course_name_template",
+            DEFAULT_COURSE_NAME_TEMPLATE,
+        );
+        assert_eq!(
+            render_zip_file_name(&config_obj, &template, date, Some(("13", "虚函数"))),
+            "CR49_张三_13-虚函数_20240520"
+        );
+        // 两个默认模板的前缀相同
+        assert_eq!(get_zip_file_name_prefixes(&config_obj), vec!["CR49_张三_"]);
+
+        let config_obj = config(
+            r#"
+            name_template = "{user}_{date}"
+            course_name_template = "{class}-{number}-{course}-{user}"
+            "#,
+        );
+        let template = get_name_template(&config_obj, "course_name_template", "");
+        assert_eq!(
+            render_zip_file_name(&config_obj, &template, date, Some(("13", "虚函数"))),
+            "CR49-13-虚函数-张三"
+        );
+        assert_eq!(
+            get_zip_file_name_prefixes(&config_obj),
+            vec!["张三_", "CR49-"]
+        );
+
+        // 没有固定前缀的模板不会匹配所有压缩包
+        let config_obj = config("course_name_template = \"{number}-{course}_{user}\"");
+        assert_eq!(get_zip_file_name_prefixes(&config_obj), vec!["CR49_张三_"]);
+    }
 }
@@ -1,5 +1,8 @@
 use clap::{Parser, Subcommand};
-use commands::{mail::Mail, new::New, zip::Zip, MyCommand};
+use commands::{
+    history::History, mail::Mail, new::New, status::Status, unzip::Unzip, verify::Verify, zip::Zip,
+    MyCommand,
+};
 use config::init_config;
 use ::tokio;
 mod commands;
@@ -18,6 +21,10 @@ enum Commands {
     New(New),
     Zip(Zip),
     Mail(Mail),
+    Verify(Verify),
+    Unzip(Unzip),
+    History(History),
+    Status(Status),
 }
 
 #[tokio::main]
@@ -44,5 +51,9 @@ fn run() {
         Commands::New(new_obj) => new_obj.run(&config_obj),
         Commands::Zip(zip_obj) => zip_obj.run(&config_obj),
         Commands::Mail(mail_obj) => mail_obj.run(&config_obj),
+        Commands::Verify(verify_obj) => verify_obj.run(&config_obj),
+        Commands::Unzip(unzip_obj) => unzip_obj.run(&config_obj),
+        Commands::History(history_obj) => history_obj.run(&config_obj),
+        Commands::Status(status_obj) => status_obj.run(&config_obj),
     }
 }
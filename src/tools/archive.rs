@@ -0,0 +1,604 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use sevenz_rust::{
+    lzma::LZMA2Options, AesEncoderOptions, Password, SevenZArchiveEntry, SevenZMethod,
+    SevenZMethodConfiguration, SevenZReader, SevenZWriter,
+};
+use sha2::{Digest, Sha256};
+use zip::{unstable::write::FileOptionsExt, write::FileOptions, AesMode, ZipArchive, ZipWriter};
+
+use super::compression::{Compression, CompressionRules};
+use super::zip_names::{rewrite_names, FilenameEncoding};
+
+/// 判断是否值得压缩时读取的文件开头的大小，更大的文件只根据开头的压缩效果选择是否直接存储
+const COMPRESSION_SAMPLE_SIZE: u64 = 64 * 1024;
+
+/// 压缩包的加密方式，对应配置 `[zip].encryption`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    /// zip AES-128，需要 7-Zip 等工具才能打开
+    Aes128,
+    /// zip AES-256，需要 7-Zip 等工具才能打开
+    Aes256,
+    /// 传统的 ZipCrypto，Windows 资源管理器可以直接打开，但安全性较弱
+    ZipCrypto,
+    /// 7z AES-256，同时加密文件名
+    SevenZAes256,
+}
+
+impl FromStr for Encryption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "aes128" => Ok(Encryption::Aes128),
+            "aes256" => Ok(Encryption::Aes256),
+            "zipcrypto" => Ok(Encryption::ZipCrypto),
+            "7z-aes256" => Ok(Encryption::SevenZAes256),
+            _ => Err(format!(
+                "未知的加密方式 {}，可选: aes128 aes256 zipcrypto 7z-aes256",
+                s
+            )),
+        }
+    }
+}
+
+impl Encryption {
+    /// 压缩包的扩展名
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Encryption::SevenZAes256 => "7z",
+            _ => "zip",
+        }
+    }
+}
+
+/// 写入压缩包时的选项
+pub struct ArchiveOptions {
+    pub encryption: Encryption,
+    /// 为空时不加密
+    pub password: String,
+    /// 固定时间戳、权限和压缩参数
+    pub reproducible: bool,
+    /// zip 文件名的编码，7z 始终使用 UTF-16
+    pub filename_encoding: FilenameEncoding,
+    /// zip 每个文件的压缩方式和压缩等级，7z 只使用其中的压缩等级
+    pub compression: CompressionRules,
+}
+
+/// 根据加密方式写入 zip 或 7z 压缩包
+pub enum ArchiveWriter {
+    Zip {
+        writer: Box<ZipWriter<File>>,
+        encryption: Encryption,
+        password: String,
+        reproducible: bool,
+        path: PathBuf,
+        filename_encoding: FilenameEncoding,
+        compression: CompressionRules,
+    },
+    SevenZ {
+        writer: Box<SevenZWriter<File>>,
+        reproducible: bool,
+    },
+}
+
+impl ArchiveWriter {
+    pub fn create(path: &Path, archive_options: &ArchiveOptions) -> Result<Self, Box<dyn Error>> {
+        let file = File::create(path)?;
+        let password = archive_options.password.as_str();
+        let reproducible = archive_options.reproducible;
+        if archive_options.encryption == Encryption::SevenZAes256 {
+            let mut writer = SevenZWriter::new(file)?;
+            let lzma2 = match archive_options.compression.level {
+                Some(level) => LZMA2Options::with_preset(level as u32).into(),
+                None => SevenZMethodConfiguration::new(SevenZMethod::LZMA2),
+            };
+            if password.is_empty() {
+                writer.set_content_methods(vec![lzma2]);
+            } else {
+                // 先加密再压缩 文件名所在的头部同样加密
+                writer.set_content_methods(vec![
+                    AesEncoderOptions::new(Password::from(password)).into(),
+                    lzma2,
+                ]);
+                writer.set_encrypt_header(true);
+            }
+            return Ok(ArchiveWriter::SevenZ {
+                writer: Box::new(writer),
+                reproducible,
+            });
+        }
+        Ok(ArchiveWriter::Zip {
+            writer: Box::new(ZipWriter::new(file)),
+            encryption: archive_options.encryption,
+            password: password.to_string(),
+            reproducible,
+            path: path.to_path_buf(),
+            filename_encoding: archive_options.filename_encoding,
+            compression: archive_options.compression.clone(),
+        })
+    }
+
+    pub fn add_directory(&mut self, name: &str, source: &Path) -> Result<(), Box<dyn Error>> {
+        match self {
+            ArchiveWriter::Zip {
+                writer,
+                encryption,
+                password,
+                reproducible,
+                ..
+            } => {
+                let mut options = zip_file_options(
+                    *encryption,
+                    password,
+                    *reproducible,
+                    Compression::Store,
+                    None,
+                );
+                if *reproducible {
+                    options = options.unix_permissions(0o755);
+                }
+                writer.add_directory(name, options)?;
+            }
+            ArchiveWriter::SevenZ {
+                writer,
+                reproducible,
+            } => {
+                let mut entry = SevenZArchiveEntry::from_path(source, name.to_string());
+                if *reproducible {
+                    entry.has_last_modified_date = false;
+                }
+                writer.push_archive_entry::<File>(entry, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn add_file(&mut self, name: &str, source: &Path) -> Result<(), Box<dyn Error>> {
+        let mut file = File::open(source)?;
+        match self {
+            ArchiveWriter::Zip {
+                writer,
+                encryption,
+                password,
+                reproducible,
+                compression,
+                ..
+            } => {
+                let level = compression.level;
+                let compression = compression.compression(name);
+                let mut options =
+                    zip_file_options(*encryption, password, *reproducible, compression, level);
+                if *reproducible {
+                    options = options.unix_permissions(0o644);
+                }
+                if compression == Compression::Store {
+                    writer.start_file_from_path(name, options)?;
+                    io::copy(&mut file, writer.as_mut())?;
+                    return Ok(());
+                }
+                // 只读取开头的一段 不加密地压缩一次 没有变小的文件直接存储 避免把整个文件读入内存
+                let mut sample = Vec::new();
+                (&mut file)
+                    .take(COMPRESSION_SAMPLE_SIZE)
+                    .read_to_end(&mut sample)?;
+                let mut scratch = ZipWriter::new(io::Cursor::new(Vec::new()));
+                let mut scratch_options =
+                    zip_file_options(*encryption, "", *reproducible, compression, level);
+                if *reproducible {
+                    scratch_options = scratch_options.unix_permissions(0o644);
+                }
+                scratch.start_file_from_path(name, scratch_options)?;
+                scratch.write_all(&sample)?;
+                let mut scratch = scratch.finish_into_readable()?;
+                let compressed = scratch.by_index_raw(0)?;
+                let is_whole_file = (sample.len() as u64) < COMPRESSION_SAMPLE_SIZE;
+                if compressed.compressed_size() >= compressed.size() {
+                    options = options
+                        .compression_method(zip::CompressionMethod::Stored)
+                        .compression_level(None);
+                } else if is_whole_file && password.is_empty() {
+                    // 小文件已经完整压缩过 不加密时直接复制压缩好的数据
+                    writer.raw_copy_file(compressed)?;
+                    return Ok(());
+                }
+                writer.start_file_from_path(name, options)?;
+                writer.write_all(&sample)?;
+                io::copy(&mut file, writer.as_mut())?;
+            }
+            ArchiveWriter::SevenZ {
+                writer,
+                reproducible,
+            } => {
+                let mut entry = SevenZArchiveEntry::from_path(source, name.to_string());
+                if *reproducible {
+                    entry.has_last_modified_date = false;
+                }
+                writer.push_archive_entry(entry, Some(file))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 写入由程序生成的内容，例如提交清单
+    pub fn add_bytes(&mut self, name: &str, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        match self {
+            ArchiveWriter::Zip {
+                writer,
+                encryption,
+                password,
+                reproducible,
+                compression,
+                ..
+            } => {
+                let mut options = zip_file_options(
+                    *encryption,
+                    password,
+                    *reproducible,
+                    compression.compression(name),
+                    compression.level,
+                );
+                if *reproducible {
+                    options = options.unix_permissions(0o644);
+                }
+                writer.start_file(name, options)?;
+                writer.write_all(data)?;
+            }
+            ArchiveWriter::SevenZ { writer, .. } => {
+                let mut entry = SevenZArchiveEntry::new();
+                entry.name = name.to_string();
+                entry.has_stream = true;
+                writer.push_archive_entry(entry, Some(data))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 设置压缩包注释，7z 不支持注释
+    pub fn set_comment(&mut self, comment: &str) {
+        if let ArchiveWriter::Zip { writer, .. } = self {
+            writer.set_comment(comment);
+        }
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            ArchiveWriter::Zip {
+                writer,
+                path,
+                filename_encoding,
+                ..
+            } => {
+                writer.finish()?;
+                rewrite_names(&path, filename_encoding)?;
+            }
+            ArchiveWriter::SevenZ { writer, .. } => {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// zip 条目的压缩和加密选项，`level` 为 0-9 的压缩等级
+fn zip_file_options(
+    encryption: Encryption,
+    password: &str,
+    reproducible: bool,
+    compression: Compression,
+    level: Option<i64>,
+) -> FileOptions<'_, ()> {
+    let mut options = FileOptions::default().compression_method(compression.method());
+    // 可复现的压缩包需要固定时间戳和压缩等级
+    if reproducible {
+        options = options.last_modified_time(zip::DateTime::default());
+    }
+    if let Some(level) = level.or(reproducible.then_some(6)) {
+        options = options.compression_level(compression.level(level));
+    }
+    if password.is_empty() {
+        return options;
+    }
+    match encryption {
+        Encryption::Aes128 => options.with_aes_encryption(AesMode::Aes128, password),
+        Encryption::Aes256 => options.with_aes_encryption(AesMode::Aes256, password),
+        _ => options.with_deprecated_encryption(password.as_bytes()),
+    }
+}
+
+/// 读取压缩包得到的一个条目
+pub struct EntryInfo {
+    /// 压缩包中的路径 去掉了文件夹末尾的 `/`
+    pub name: String,
+    pub is_dir: bool,
+    /// 解压后的大小
+    pub size: u64,
+    /// 解压后内容的 SHA-256
+    pub sha256: String,
+    /// 解压或CRC校验失败的原因
+    pub error: Option<String>,
+}
+
+/// 7z 文件头的签名
+const SEVEN_Z_SIGNATURE: &[u8; 6] = b"7z\xBC\xAF\x27\x1C";
+
+/// 通过文件头判断是否是 7z 压缩包
+pub fn is_7z(path: &Path) -> io::Result<bool> {
+    let mut signature = [0u8; 6];
+    let mut file = File::open(path)?;
+    match file.read_exact(&mut signature) {
+        Ok(()) => Ok(&signature == SEVEN_Z_SIGNATURE),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// 逐个解压(如有密码则解密)压缩包中的条目并校验CRC，根据文件头区分 zip 和 7z
+pub fn read_entries(path: &Path, password: &str) -> Result<Vec<EntryInfo>, Box<dyn Error>> {
+    if is_7z(path)? {
+        return read_7z_entries(path, password);
+    }
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let raw_name = archive.name_for_index(i).unwrap_or_default().to_string();
+        let file = if password.is_empty() {
+            archive.by_index(i)
+        } else {
+            archive.by_index_decrypt(i, password.as_bytes())
+        };
+        let mut file = match file {
+            Ok(file) => file,
+            Err(err) => {
+                entries.push(EntryInfo {
+                    name: raw_name,
+                    is_dir: false,
+                    size: 0,
+                    sha256: String::new(),
+                    error: Some(err.to_string()),
+                });
+                continue;
+            }
+        };
+        let name = decode_zip_name(file.name(), file.name_raw())
+            .trim_end_matches('/')
+            .to_string();
+        let is_dir = file.is_dir();
+        // 读取全部内容 zip会在读取结束时校验CRC
+        let mut hasher = Sha256::new();
+        let (size, error) = match io::copy(&mut file, &mut hasher) {
+            Ok(size) => (size, None),
+            Err(err) => (0, Some(err.to_string())),
+        };
+        entries.push(EntryInfo {
+            name,
+            is_dir,
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+            error,
+        });
+    }
+    Ok(entries)
+}
+
+fn read_7z_entries(path: &Path, password: &str) -> Result<Vec<EntryInfo>, Box<dyn Error>> {
+    let mut reader = SevenZReader::open(path, Password::from(password))?;
+    let mut entries = Vec::new();
+    // 7z 在读取每个条目结束时校验CRC，失败时直接返回错误
+    reader.for_each_entries(|entry, data: &mut dyn Read| {
+        let mut hasher = Sha256::new();
+        let size = io::copy(data, &mut hasher)?;
+        entries.push(EntryInfo {
+            name: entry.name().trim_end_matches('/').to_string(),
+            is_dir: entry.is_directory(),
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+            error: None,
+        });
+        Ok(true)
+    })?;
+    Ok(entries)
+}
+
+/// 读取压缩包中一个文件的内容，不存在时返回 `None`
+pub fn read_file(
+    path: &Path,
+    password: &str,
+    name: &str,
+) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let mut content = None;
+    if is_7z(path)? {
+        let mut reader = SevenZReader::open(path, Password::from(password))?;
+        reader.for_each_entries(|entry, data: &mut dyn Read| {
+            if entry.name() != name {
+                // 固实压缩需要按顺序解压 跳过的内容也要读取
+                io::copy(data, &mut io::sink())?;
+                return Ok(true);
+            }
+            let mut buf = Vec::new();
+            data.read_to_end(&mut buf)?;
+            content = Some(buf);
+            Ok(false)
+        })?;
+        return Ok(content);
+    }
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let Some(index) = archive.index_for_name(name) else {
+        return Ok(None);
+    };
+    let mut file = if password.is_empty() {
+        archive.by_index(index)?
+    } else {
+        archive.by_index_decrypt(index, password.as_bytes())?
+    };
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    Ok(Some(buf))
+}
+
+/// 读取 zip 压缩包的注释，7z 没有注释
+pub fn read_comment(path: &Path) -> Result<String, Box<dyn Error>> {
+    if is_7z(path)? {
+        return Ok(String::new());
+    }
+    let archive = ZipArchive::new(File::open(path)?)?;
+    Ok(String::from_utf8_lossy(archive.comment()).to_string())
+}
+
+/// 解压时压缩包中的一个条目
+pub struct ExtractEntry {
+    /// 解码后的文件名，`\` 统一替换为 `/`
+    pub name: String,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+}
+
+/// zip 条目的文件名，没有 UTF-8 标记时依次尝试 UTF-8 和 GB18030(兼容 GBK)，
+/// 都失败时使用 zip 默认的 CP437 解码结果
+/// 有 Unicode Path 扩展字段时 zip 库会把 `name` 和 `raw` 都替换为扩展字段中的 UTF-8 文件名，
+/// 这时 `raw` 不再是文件头中的原始内容
+pub fn decode_zip_name(name: &str, raw: &[u8]) -> String {
+    // 有 UTF-8 标记时 name 按 UTF-8 解码自 raw，有 Unicode Path 扩展字段时两者都是扩展字段的内容
+    let name = if raw.is_ascii() || name.as_bytes() == raw {
+        name.to_string()
+    } else if let Ok(utf8) = std::str::from_utf8(raw) {
+        utf8.to_string()
+    } else {
+        let (decoded, _, had_errors) = encoding_rs::GB18030.decode(raw);
+        match had_errors {
+            true => name.to_string(),
+            false => decoded.into_owned(),
+        }
+    };
+    name.replace('\\', "/")
+}
+
+/// 压缩包是否需要密码
+pub fn needs_password(path: &Path) -> Result<bool, Box<dyn Error>> {
+    if is_7z(path)? {
+        return match SevenZReader::open(path, Password::empty()) {
+            Ok(reader) => Ok(reader.archive().folders.iter().any(|folder| {
+                folder
+                    .coders
+                    .iter()
+                    .any(|coder| coder.decompression_method_id() == SevenZMethod::ID_AES256SHA256)
+            })),
+            Err(sevenz_rust::Error::PasswordRequired) => Ok(true),
+            Err(err) => Err(err.into()),
+        };
+    }
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    for i in 0..archive.len() {
+        if archive.by_index_raw(i)?.encrypted() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// 列出压缩包中的条目，不解压内容
+pub fn list_extract_entries(
+    path: &Path,
+    password: &str,
+) -> Result<Vec<ExtractEntry>, Box<dyn Error>> {
+    if is_7z(path)? {
+        let reader = SevenZReader::open(path, Password::from(password))?;
+        return Ok(reader
+            .archive()
+            .files
+            .iter()
+            .map(|entry| ExtractEntry {
+                name: entry.name().replace('\\', "/"),
+                is_dir: entry.is_directory(),
+                is_symlink: false,
+            })
+            .collect());
+    }
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        entries.push(ExtractEntry {
+            name: decode_zip_name(file.name(), file.name_raw()),
+            is_dir: file.is_dir(),
+            is_symlink: file.is_symlink(),
+        });
+    }
+    Ok(entries)
+}
+
+/// 逐个解压压缩包中的文件，`f` 接收与 [`list_extract_entries`] 相同的解码后的文件名和文件内容
+pub fn extract_files<F>(path: &Path, password: &str, mut f: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut(&str, &mut dyn Read) -> io::Result<()>,
+{
+    if is_7z(path)? {
+        let mut reader = SevenZReader::open(path, Password::from(password))?;
+        reader.for_each_entries(|entry, data: &mut dyn Read| {
+            if !entry.is_directory() {
+                f(&entry.name().replace('\\', "/"), data)?;
+            }
+            Ok(true)
+        })?;
+        return Ok(());
+    }
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    for i in 0..archive.len() {
+        let mut file = if password.is_empty() {
+            archive.by_index(i)?
+        } else {
+            archive.by_index_decrypt(i, password.as_bytes())?
+        };
+        if file.is_dir() || file.is_symlink() {
+            continue;
+        }
+        let name = decode_zip_name(file.name(), file.name_raw());
+        f(&name, &mut file)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tools::files::TempDir;
+
+    #[test]
+    fn decode_legacy_names() {
+        // 没有 UTF-8 标记时 zip 按 CP437 解码得到的是乱码
+        let gbk = encoding_rs::GBK.encode("起始代码\\主程序.cpp").0;
+        assert_eq!(decode_zip_name("garbled", &gbk), "起始代码/主程序.cpp");
+        let utf8 = "说明.txt".as_bytes();
+        assert_eq!(decode_zip_name("garbled", utf8), "说明.txt");
+        assert_eq!(decode_zip_name("说明.txt", utf8), "说明.txt");
+        assert_eq!(decode_zip_name("a/b.txt", b"a/b.txt"), "a/b.txt");
+    }
+
+    #[test]
+    fn decode_unicode_path_names() {
+        let temp = TempDir::new("unicode-path").unwrap();
+        let path = temp.0.join("names.zip");
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+        writer
+            .start_file("作业/说明.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.finish().unwrap();
+        rewrite_names(&path, FilenameEncoding::GbkUnicodePath).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let file = archive.by_index(0).unwrap();
+        // 文件头中是 GBK 文件名 读取到的 raw 是扩展字段中的 UTF-8 文件名
+        assert_eq!(file.name_raw(), "作业/说明.txt".as_bytes());
+        assert_eq!(
+            decode_zip_name(file.name(), file.name_raw()),
+            "作业/说明.txt"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use std::str::FromStr;
+
+use glob::{MatchOptions, Pattern};
+use toml::Table;
+
+/// 扩展名不区分大小写
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// 默认直接存储的已经压缩过的文件，`[zip.compression]` 中的规则优先
+const DEFAULT_STORED: &[&str] = &[
+    "*.png", "*.jpg", "*.jpeg", "*.gif", "*.webp", "*.mp3", "*.mp4", "*.mkv", "*.avi", "*.mov",
+    "*.zip", "*.7z", "*.rar", "*.gz", "*.xz", "*.bz2", "*.zst", "*.docx", "*.xlsx", "*.pptx",
+];
+
+/// zip 条目的压缩方式，对应 `[zip.compression]` 中的值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Store,
+    Deflate,
+    Bzip2,
+    Zstd,
+    /// xz(LZMA2)，7-Zip、Bandizip 可以解压
+    Xz,
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "store" => Ok(Compression::Store),
+            "deflate" => Ok(Compression::Deflate),
+            "bzip2" => Ok(Compression::Bzip2),
+            "zstd" => Ok(Compression::Zstd),
+            "xz" => Ok(Compression::Xz),
+            // zip 库只能解压 lzma(方法 14) 不能用 lzma 压缩 不自动替换为其他压缩方式
+            "lzma" => Err(
+                "暂不支持 lzma: 使用的 zip 库(zip 2.x)只能解压 lzma 条目，不能写入。需要 LZMA 算法时请显式改为 xz(LZMA2)，或者使用 7z-aes256 生成 7z 压缩包"
+                    .to_string(),
+            ),
+            _ => Err(format!(
+                "未知的压缩方式 {}，可选: store deflate bzip2 zstd xz",
+                s
+            )),
+        }
+    }
+}
+
+impl Compression {
+    pub fn method(&self) -> zip::CompressionMethod {
+        match self {
+            Compression::Store => zip::CompressionMethod::Stored,
+            Compression::Deflate => zip::CompressionMethod::Deflated,
+            Compression::Bzip2 => zip::CompressionMethod::Bzip2,
+            Compression::Zstd => zip::CompressionMethod::Zstd,
+            Compression::Xz => zip::CompressionMethod::Xz,
+        }
+    }
+
+    /// 把 0-9 的压缩等级转换为这种压缩方式支持的等级
+    pub fn level(&self, level: i64) -> Option<i64> {
+        match self {
+            Compression::Store => None,
+            // deflate 和 bzip2 最低为 1
+            Compression::Deflate | Compression::Bzip2 => Some(level.max(1)),
+            // zstd 的等级为 1-22
+            Compression::Zstd => Some(match level {
+                0 => 1,
+                level => level * 2,
+            }),
+            Compression::Xz => Some(level),
+        }
+    }
+}
+
+/// `--level` 以及 `[zip.compression]` 中配置的压缩规则
+#[derive(Debug, Clone)]
+pub struct CompressionRules {
+    /// 0-9，未指定时使用每种压缩方式的默认等级
+    pub level: Option<i64>,
+    rules: Vec<(Pattern, Compression)>,
+}
+
+impl CompressionRules {
+    /// 由 `[zip.compression]` 表生成规则，键为匹配文件路径的规则，值为压缩方式
+    /// 多个规则匹配时使用最长的规则，没有匹配的规则时使用 deflate
+    /// 已经压缩过的图片、视频、压缩包默认直接存储
+    pub fn from_table(table: Option<&Table>, level: Option<i64>) -> Result<Self, String> {
+        if let Some(level) = level {
+            if !(0..=9).contains(&level) {
+                return Err(format!("压缩等级必须在 0-9 之间: {}", level));
+            }
+        }
+        let mut rules = Vec::new();
+        for (pattern, method) in table.into_iter().flatten() {
+            let method = method
+                .as_str()
+                .ok_or(format!("[zip.compression] 中的 {} 必须是字符串", pattern))?
+                .parse::<Compression>()?;
+            let pattern = Pattern::new(pattern)
+                .map_err(|err| format!("无效的匹配规则 {}: {}", pattern, err))?;
+            rules.push((pattern, method));
+        }
+        // 配置表中的字段按名称排序 无法保留书写顺序
+        rules.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.as_str().len()));
+        for pattern in DEFAULT_STORED {
+            rules.push((Pattern::new(pattern).unwrap(), Compression::Store));
+        }
+        Ok(CompressionRules { level, rules })
+    }
+
+    /// 压缩包中的文件使用的压缩方式
+    pub fn compression(&self, name: &str) -> Compression {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| pattern.matches_with(name, MATCH_OPTIONS))
+            .map(|(_, compression)| *compression)
+            .unwrap_or(Compression::Deflate)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn configured_rules_take_precedence() {
+        let table = toml::from_str::<Table>(
+            r#"
+            "*.png" = "deflate"
+            "data/**" = "xz"
+            "*.txt" = "zstd"
+            "#,
+        )
+        .unwrap();
+        let rules = CompressionRules::from_table(Some(&table), Some(9)).unwrap();
+        assert_eq!(rules.compression("img/a.PNG"), Compression::Deflate);
+        assert_eq!(rules.compression("data/a.bin"), Compression::Xz);
+        assert_eq!(rules.compression("note.txt"), Compression::Zstd);
+        assert_eq!(rules.compression("video/a.mp4"), Compression::Store);
+        assert_eq!(rules.compression("main.cpp"), Compression::Deflate);
+
+        let lzma = toml::from_str::<Table>(r#""*" = "lzma""#).unwrap();
+        assert!(CompressionRules::from_table(Some(&lzma), None).is_err());
+        assert!(CompressionRules::from_table(None, Some(10)).is_err());
+    }
+}
@@ -1,6 +1,5 @@
 
 use chrono::{DateTime, Local};
-use std::time::SystemTime;
 use lettre::message::header::{Header, HeaderName, HeaderValue};
 use std::error::Error as StdError;
 
@@ -10,7 +9,7 @@ pub struct Date(DateTime<Local>);
 impl Date {
     /// Build a `Date` from [`SystemTime`]
     pub fn new(st: DateTime<Local>) -> Self {
-        Self(st.into())
+        Self(st)
     }
 
     /// Get the current date
@@ -41,7 +40,7 @@ impl Header for Date {
 #[cfg(test)]
 mod test {
     use super::*;
-    use lettre::message::header::{HeaderName, HeaderValue, Headers};
+    use lettre::message::header::Headers;
     #[test]
     fn format_date() {
         let mut headers = Headers::new();
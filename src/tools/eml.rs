@@ -0,0 +1,707 @@
+use base64::Engine;
+use lettre::message::header::{Header, HeaderName, HeaderValue};
+
+/// RFC 2047 每个编码词编码前的最大字节数，编码后为 64 个字符(RFC 2047 限制为 75)
+/// 加上 ` filename="` 后一行不超过 78 个字符
+const ENCODED_WORD_BYTES: usize = 39;
+/// RFC 2231 分段参数中每一段的值的最大长度，加上参数名后一行不超过 78 个字符
+const MAX_SECTION_LEN: usize = 60;
+
+/// 解析后的原始邮件，只保留发送和记录历史需要的信息
+#[derive(Debug, Default)]
+pub struct Eml {
+    /// 邮件头，按出现的顺序，折叠的行已经合并
+    pub headers: Vec<(String, String)>,
+    /// 附件的文件名和解码后的内容
+    pub attachments: Vec<(String, Vec<u8>)>,
+}
+
+impl Eml {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let (headers, body) = split_message(data);
+        if headers.is_empty() {
+            return Err("没有邮件头，不是有效的 eml 文件".to_string());
+        }
+        let mut attachments = Vec::new();
+        collect_attachments(&headers, body, &mut attachments)?;
+        Ok(Eml {
+            headers,
+            attachments,
+        })
+    }
+
+    /// 第一个同名的邮件头，不区分大小写
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// 所有同名邮件头中的邮箱地址，例如 To 可能出现多次
+    pub fn addresses(&self, name: &str) -> Vec<String> {
+        self.headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .flat_map(|(_, value)| address_list(value))
+            .collect()
+    }
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// 拆分邮件头和正文，邮件头中以空白开头的行是上一行的延续
+fn split_message(data: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let end = rest
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(rest.len(), |i| i + 1);
+        let line = String::from_utf8_lossy(&rest[..end]);
+        let line = line.trim_end_matches(['\r', '\n']);
+        rest = &rest[end..];
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    (headers, rest)
+}
+
+/// 删除邮件中所有同名的邮件头(包括折叠的行)，不区分大小写，其余内容原样保留
+/// 例如发送前删除 Bcc，避免其他收件人看到密送地址
+pub fn remove_header(data: &[u8], name: &str) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut rest = data;
+    let mut removing = false;
+    while !rest.is_empty() {
+        let end = rest
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(rest.len(), |i| i + 1);
+        let line = &rest[..end];
+        rest = &rest[end..];
+        if line == b"\n" || line == b"\r\n" {
+            result.extend_from_slice(line);
+            break;
+        }
+        if !line.starts_with(b" ") && !line.starts_with(b"\t") {
+            removing = line
+                .iter()
+                .position(|b| *b == b':')
+                .is_some_and(|i| line[..i].trim_ascii().eq_ignore_ascii_case(name.as_bytes()));
+        }
+        if !removing {
+            result.extend_from_slice(line);
+        }
+    }
+    result.extend_from_slice(rest);
+    result
+}
+
+/// 递归查找 multipart 中的附件
+fn collect_attachments(
+    headers: &[(String, String)],
+    body: &[u8],
+    attachments: &mut Vec<(String, Vec<u8>)>,
+) -> Result<(), String> {
+    let content_type = header(headers, "Content-Type").unwrap_or("text/plain");
+    if content_type.to_ascii_lowercase().starts_with("multipart/") {
+        let boundary = parameter(content_type, "boundary")
+            .ok_or_else(|| format!("multipart 缺少 boundary: {}", content_type))?;
+        for part in split_parts(body, &boundary) {
+            let (part_headers, part_body) = split_message(part);
+            collect_attachments(&part_headers, part_body, attachments)?;
+        }
+        return Ok(());
+    }
+    let disposition = header(headers, "Content-Disposition").unwrap_or_default();
+    let Some(name) = parameter(disposition, "filename").or_else(|| parameter(content_type, "name"))
+    else {
+        return Ok(());
+    };
+    let encoding = header(headers, "Content-Transfer-Encoding").unwrap_or("7bit");
+    let data = match encoding.to_ascii_lowercase().as_str() {
+        "base64" => {
+            let text = body
+                .iter()
+                .filter(|b| !b.is_ascii_whitespace())
+                .copied()
+                .collect::<Vec<u8>>();
+            base64::engine::general_purpose::STANDARD
+                .decode(text)
+                .map_err(|err| format!("附件 {} 的 base64 内容错误: {}", name, err))?
+        }
+        "quoted-printable" => decode_quoted_printable(body, false),
+        _ => body.strip_suffix(b"\r\n").unwrap_or(body).to_vec(),
+    };
+    attachments.push((name, data));
+    Ok(())
+}
+
+/// 按 boundary 拆分 multipart 的各个部分，不包含前言和结尾
+fn split_parts<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+    while offset < body.len() {
+        let end = body[offset..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(body.len(), |i| offset + i + 1);
+        let line = String::from_utf8_lossy(&body[offset..end]);
+        let line = line.trim_end();
+        if line.starts_with(&delimiter) {
+            if let Some(start) = start {
+                // 分隔行之前的换行属于分隔行
+                let part = &body[start..offset];
+                let part = part.strip_suffix(b"\n").unwrap_or(part);
+                parts.push(part.strip_suffix(b"\r").unwrap_or(part));
+            }
+            if line[delimiter.len()..].starts_with("--") {
+                return parts;
+            }
+            start = Some(end);
+        }
+        offset = end;
+    }
+    parts
+}
+
+/// 读取 `Content-Type` `Content-Disposition` 中的参数
+/// 支持 RFC 2231 的 `name*=utf-8''...` 和分段的 `name*0*=`，以及 RFC 2047 编码的值
+pub fn parameter(value: &str, name: &str) -> Option<String> {
+    let mut plain = None;
+    let mut extended = None;
+    let mut sections: Vec<(u32, bool, String)> = Vec::new();
+    for (key, value) in split_parameters(value) {
+        let key = key.to_ascii_lowercase();
+        let Some(rest) = key.strip_prefix(&name.to_ascii_lowercase()) else {
+            continue;
+        };
+        if rest.is_empty() {
+            plain = Some(value);
+        } else if rest == "*" {
+            extended = Some(value);
+        } else if let Some(section) = rest.strip_prefix('*') {
+            let (index, is_encoded) = match section.strip_suffix('*') {
+                Some(index) => (index, true),
+                None => (section, false),
+            };
+            if let Ok(index) = index.parse() {
+                sections.push((index, is_encoded, value));
+            }
+        }
+    }
+    if let Some(value) = extended {
+        return Some(decode_extended(&[(true, value)]));
+    }
+    if !sections.is_empty() {
+        sections.sort_by_key(|(index, _, _)| *index);
+        let sections = sections
+            .into_iter()
+            .map(|(_, is_encoded, value)| (is_encoded, value))
+            .collect::<Vec<_>>();
+        return Some(decode_extended(&sections));
+    }
+    plain.map(|value| decode_words(&value))
+}
+
+/// 拆分 `;` 分隔的参数，引号中的 `;` 不拆分
+fn split_parameters(value: &str) -> Vec<(String, String)> {
+    let mut parameters = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut chars = value.chars();
+    let mut items = Vec::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            '\\' if in_quotes => {
+                current.push(c);
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            ';' if !in_quotes => items.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    items.push(current);
+    // 第一项是类型本身 例如 attachment 或 text/plain
+    for item in items.into_iter().skip(1) {
+        let Some((key, value)) = item.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+            None => value.to_string(),
+        };
+        parameters.push((key.trim().to_string(), value));
+    }
+    parameters
+}
+
+/// 解码 RFC 2231 的参数值，第一段为 `charset'language'%XX...`
+fn decode_extended(sections: &[(bool, String)]) -> String {
+    let mut charset = "utf-8".to_string();
+    let mut bytes = Vec::new();
+    for (index, (is_encoded, value)) in sections.iter().enumerate() {
+        if !is_encoded {
+            bytes.extend_from_slice(value.as_bytes());
+            continue;
+        }
+        let mut value = value.as_str();
+        if index == 0 {
+            let mut fields = value.splitn(3, '\'');
+            if let (Some(first), Some(_), Some(rest)) =
+                (fields.next(), fields.next(), fields.next())
+            {
+                if !first.is_empty() {
+                    charset = first.to_string();
+                }
+                value = rest;
+            }
+        }
+        let raw = value.as_bytes();
+        let mut i = 0;
+        while i < raw.len() {
+            if raw[i] == b'%' && i + 2 < raw.len() {
+                let hex = std::str::from_utf8(&raw[i + 1..i + 3]).ok();
+                if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    bytes.push(byte);
+                    i += 3;
+                    continue;
+                }
+            }
+            bytes.push(raw[i]);
+            i += 1;
+        }
+    }
+    decode_charset(&charset, &bytes)
+}
+
+/// 解码 RFC 2047 编码的文本，例如 `=?utf-8?B?5byg5LiJ?=`，相邻编码词之间的空白会被忽略
+pub fn decode_words(value: &str) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+    let mut pending_space = String::new();
+    let mut last_was_word = false;
+    while !rest.is_empty() {
+        if let Some((decoded, len)) = decode_word(rest) {
+            if !last_was_word {
+                result.push_str(&pending_space);
+            }
+            pending_space.clear();
+            result.push_str(&decoded);
+            rest = &rest[len..];
+            last_was_word = true;
+            continue;
+        }
+        let c = rest.chars().next().unwrap();
+        rest = &rest[c.len_utf8()..];
+        if c.is_whitespace() {
+            pending_space.push(c);
+            continue;
+        }
+        result.push_str(&pending_space);
+        pending_space.clear();
+        result.push(c);
+        last_was_word = false;
+    }
+    result.push_str(&pending_space);
+    result
+}
+
+/// 解码开头的一个编码词，返回解码后的文本和编码词的长度
+fn decode_word(value: &str) -> Option<(String, usize)> {
+    let inner = value.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let text = &inner[..end];
+    if text.contains(char::is_whitespace) {
+        return None;
+    }
+    let bytes = match encoding {
+        "B" | "b" => base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .ok()?,
+        "Q" | "q" => decode_quoted_printable(text.as_bytes(), true),
+        _ => return None,
+    };
+    // RFC 2231 允许在字符集后面加上语言 例如 utf-8*zh
+    let charset = charset.split('*').next().unwrap();
+    let len = value.len() - inner.len() + end + 2;
+    Some((decode_charset(charset, &bytes), len))
+}
+
+fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    match encoding_rs::Encoding::for_label(charset.as_bytes()) {
+        Some(encoding) => encoding.decode(bytes).0.into_owned(),
+        None => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// 解码 quoted-printable，`is_header` 时 `_` 表示空格
+fn decode_quoted_printable(data: &[u8], is_header: bool) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            b'=' if data[i + 1..].starts_with(b"\r\n") => i += 3,
+            b'=' if data[i + 1..].starts_with(b"\n") => i += 2,
+            b'=' if i + 2 < data.len() => {
+                match std::str::from_utf8(&data[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        bytes.push(byte);
+                        i += 3;
+                    }
+                    None => {
+                        bytes.push(b'=');
+                        i += 1;
+                    }
+                }
+            }
+            b'_' if is_header => {
+                bytes.push(b' ');
+                i += 1;
+            }
+            byte => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    bytes
+}
+
+/// 附件的 `Content-Disposition`，文件名同时使用两种编码
+/// `filename*=` 为 RFC 2231 编码，名称较长时分为 `filename*0*=` `filename*1*=` ...
+/// `filename="=?utf-8?B?...?="` 为 RFC 2047 编码，给不支持 RFC 2231 的客户端使用
+#[derive(Debug, Clone)]
+pub struct AttachmentDisposition {
+    file_name: String,
+}
+
+impl AttachmentDisposition {
+    pub fn new(file_name: &str) -> Self {
+        AttachmentDisposition {
+            file_name: file_name.to_string(),
+        }
+    }
+}
+
+impl Header for AttachmentDisposition {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Content-Disposition")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let file_name = parameter(s, "filename").ok_or("缺少 filename")?;
+        Ok(AttachmentDisposition { file_name })
+    }
+
+    fn display(&self) -> HeaderValue {
+        let mut encoded = "attachment".to_string();
+        for parameter in filename_parameters("filename", &self.file_name) {
+            encoded.push_str(";\r\n ");
+            encoded.push_str(&parameter);
+        }
+        HeaderValue::dangerous_new_pre_encoded(
+            Self::name(),
+            format!("attachment; filename=\"{}\"", self.file_name),
+            encoded,
+        )
+    }
+}
+
+/// 附件的 `Content-Type`，`name` 参数使用 RFC 2047 编码，一些旧的客户端只读取这个参数
+#[derive(Debug, Clone)]
+pub struct AttachmentContentType {
+    mime: String,
+    file_name: String,
+}
+
+impl AttachmentContentType {
+    pub fn new(mime: &str, file_name: &str) -> Self {
+        AttachmentContentType {
+            mime: mime.to_string(),
+            file_name: file_name.to_string(),
+        }
+    }
+}
+
+impl Header for AttachmentContentType {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Content-Type")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mime = s.split(';').next().unwrap().trim();
+        let file_name = parameter(s, "name").ok_or("缺少 name")?;
+        Ok(AttachmentContentType::new(mime, &file_name))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::dangerous_new_pre_encoded(
+            Self::name(),
+            format!("{}; name=\"{}\"", self.mime, self.file_name),
+            format!(
+                "{};\r\n name={}",
+                self.mime,
+                quote(&fold_words(&self.file_name))
+            ),
+        )
+    }
+}
+
+/// 文件名参数，ASCII 文件名直接放在引号中
+/// 其他文件名为 RFC 2047 编码的 `key="..."` 加上 RFC 2231 编码的 `key*=...`
+pub fn filename_parameters(key: &str, file_name: &str) -> Vec<String> {
+    let is_plain = file_name
+        .chars()
+        .all(|c| c.is_ascii() && !c.is_ascii_control());
+    if is_plain && file_name.len() <= MAX_SECTION_LEN {
+        return vec![format!("{}={}", key, quote(file_name))];
+    }
+    let mut parameters = vec![format!("{}={}", key, quote(&fold_words(file_name)))];
+    // 按字符分段 不会把一个字符的 UTF-8 字节分到两段中
+    let mut sections = vec!["utf-8''".to_string()];
+    for c in file_name.chars() {
+        let mut buf = [0u8; 4];
+        let encoded = c
+            .encode_utf8(&mut buf)
+            .bytes()
+            .map(|byte| match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => (byte as char).to_string(),
+                b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|'
+                | b'~' => (byte as char).to_string(),
+                _ => format!("%{:02X}", byte),
+            })
+            .collect::<String>();
+        if sections.last().unwrap().len() + encoded.len() > MAX_SECTION_LEN {
+            sections.push(String::new());
+        }
+        sections.last_mut().unwrap().push_str(&encoded);
+    }
+    if sections.len() == 1 {
+        parameters.push(format!("{}*={}", key, sections[0]));
+    } else {
+        parameters.extend(
+            sections
+                .iter()
+                .enumerate()
+                .map(|(index, section)| format!("{}*{}*={}", key, index, section)),
+        );
+    }
+    parameters
+}
+
+/// 加上引号，转义其中的引号和反斜杠
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// RFC 2047 编码后在编码词之间折行，解码时折行处的空白会被忽略
+fn fold_words(text: &str) -> String {
+    encode_words(text).replace(' ', "\r\n ")
+}
+
+/// RFC 2047 编码，按字符拆分为多个编码词，不会把一个字符的 UTF-8 字节分到两个编码词中
+pub fn encode_words(text: &str) -> String {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        if current.len() + c.len_utf8() > ENCODED_WORD_BYTES {
+            words.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    words.push(current);
+    words
+        .iter()
+        .map(|word| {
+            format!(
+                "=?utf-8?B?{}?=",
+                base64::engine::general_purpose::STANDARD.encode(word.as_bytes())
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// 地址列表中的邮箱地址，例如 `"张老师" <teacher@qq.com>, ta@qq.com`
+/// 不是邮箱地址的部分(例如 `undisclosed-recipients:;`)会被忽略
+pub fn address_list(value: &str) -> Vec<String> {
+    let mut addresses = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut in_angle = false;
+    for c in value.chars().chain(std::iter::once(',')) {
+        match c {
+            '"' if !in_angle => in_quotes = !in_quotes,
+            '<' if !in_quotes => {
+                in_angle = true;
+                current.clear();
+            }
+            '>' if in_angle => in_angle = false,
+            ',' if !in_quotes && !in_angle => {
+                let address = current.trim();
+                if address.contains('@') {
+                    addresses.push(address.to_string());
+                }
+                current.clear();
+            }
+            c if in_angle || !in_quotes => current.push(c),
+            _ => {}
+        }
+    }
+    addresses
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lettre::message::{header::ContentType, Attachment, MultiPart, SinglePart};
+    use lettre::Message;
+
+    #[test]
+    fn parse_generated_message() {
+        let message = Message::builder()
+            .from("张三 <me@example.com>".parse().unwrap())
+            .to("\"张老师, 助教\" <teacher@example.com>".parse().unwrap())
+            .cc("ta@example.com".parse().unwrap())
+            .subject("[重交 v2] CR49_张三_20240520")
+            .message_id(None)
+            .multipart(
+                MultiPart::mixed()
+                    .singlepart(SinglePart::plain("正文".to_string()))
+                    .singlepart(
+                        Attachment::new("笔记.md".to_string())
+                            .body(b"# notes\n".to_vec(), ContentType::TEXT_PLAIN),
+                    )
+                    .singlepart(
+                        Attachment::new("a.zip".to_string())
+                            .body(vec![0u8, 1, 2, 255], "application/zip".parse().unwrap()),
+                    ),
+            )
+            .unwrap();
+        let eml = Eml::parse(&message.formatted()).unwrap();
+        assert_eq!(
+            decode_words(eml.header("subject").unwrap()),
+            "[重交 v2] CR49_张三_20240520"
+        );
+        assert_eq!(eml.addresses("From"), vec!["me@example.com"]);
+        assert_eq!(eml.addresses("To"), vec!["teacher@example.com"]);
+        assert_eq!(eml.addresses("Cc"), vec!["ta@example.com"]);
+        assert!(eml.header("Message-ID").is_some());
+        assert_eq!(
+            eml.attachments,
+            vec![
+                ("笔记.md".to_string(), b"# notes\n".to_vec()),
+                ("a.zip".to_string(), vec![0u8, 1, 2, 255]),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_parameters() {
+        let disposition = "attachment; filename*=utf-8''%E7%AC%94%E8%AE%B0.md";
+        assert_eq!(parameter(disposition, "filename").unwrap(), "笔记.md");
+        let disposition =
+            "attachment;\r\n filename*0*=utf-8''%E7%AC%94; filename*1*=%E8%AE%B0; filename*2=.md";
+        assert_eq!(parameter(disposition, "filename").unwrap(), "笔记.md");
+        let content_type = "application/zip; name=\"=?gb18030?B?scq8xw==?=.zip\"";
+        assert_eq!(parameter(content_type, "name").unwrap(), "笔记.zip");
+        assert_eq!(decode_words("=?utf-8?Q?a_b?= =?utf-8?Q?c?= d"), "a bc d");
+        assert_eq!(
+            address_list("undisclosed-recipients:;, a@b.com"),
+            vec!["a@b.com"]
+        );
+    }
+
+    #[test]
+    fn attachment_names_round_trip() {
+        let long_name = format!(
+            "{}.zip",
+            "CR49_张三_13-虚函数与纯虚函数的区别以及虚函数表的实现原理".repeat(3)
+        );
+        let names = [
+            "a.zip",
+            "笔记.md",
+            "CR49_张三_20240520.zip",
+            "报告 \"最终版\" (2).docx",
+            "emoji-📦.7z",
+            long_name.as_str(),
+        ];
+        for name in names {
+            let part = SinglePart::builder()
+                .header(AttachmentContentType::new("application/zip", name))
+                .header(AttachmentDisposition::new(name))
+                .body(b"data".to_vec());
+            let message = Message::builder()
+                .from("me@example.com".parse().unwrap())
+                .to("teacher@example.com".parse().unwrap())
+                .subject("test")
+                .multipart(MultiPart::mixed().singlepart(part))
+                .unwrap();
+            let data = message.formatted();
+            let text = String::from_utf8(data.clone()).unwrap();
+            assert!(text.is_ascii(), "{}", text);
+            assert!(text.lines().all(|line| line.len() <= 998));
+            let eml = Eml::parse(&data).unwrap();
+            assert_eq!(eml.attachments, vec![(name.to_string(), b"data".to_vec())]);
+
+            // 不支持 RFC 2231 的客户端读取 filename 和 name
+            let part = &text[text.find("Content-Type: application/zip").unwrap()..];
+            let header_lines = part.split("\r\n\r\n").next().unwrap();
+            assert!(
+                header_lines.lines().all(|line| line.len() <= 78),
+                "{}",
+                header_lines
+            );
+            let (headers, _) = split_message(part.as_bytes());
+            assert_eq!(
+                parameter(header(&headers, "Content-Type").unwrap(), "name").unwrap(),
+                name
+            );
+            let disposition = header(&headers, "Content-Disposition").unwrap();
+            let fallback = disposition
+                .split(';')
+                .filter(|parameter| !parameter.contains("filename*"))
+                .collect::<Vec<&str>>()
+                .join(";");
+            assert_eq!(parameter(&fallback, "filename").unwrap(), name);
+        }
+        // 较长的名称分段，每个编码词不超过 75 个字符
+        let parameters = filename_parameters("filename", &long_name);
+        assert!(parameters
+            .iter()
+            .any(|p| p.starts_with("filename*0*=utf-8''")));
+        assert!(encode_words(&long_name)
+            .split(' ')
+            .all(|word| word.len() <= 75));
+    }
+}
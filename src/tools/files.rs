@@ -0,0 +1,97 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use sha2::{Digest, Sha256};
+
+/// 计算文件的 SHA-256，返回小写十六进制字符串
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// 将字节数格式化为便于阅读的大小，例如: 1.5 MB
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+/// 解析配置中的大小，支持整数字节数或带单位的字符串，例如: 512, "200KB", "50MB", "1G"
+pub fn parse_size(value: &toml::Value) -> Option<u64> {
+    if let Some(size) = value.as_integer() {
+        return u64::try_from(size).ok();
+    }
+    let text = value.as_str()?.trim().to_uppercase();
+    let text = text.strip_suffix('B').unwrap_or(&text);
+    let (number, multiple) = match text.chars().last()? {
+        'K' => (&text[..text.len() - 1], 1024),
+        'M' => (&text[..text.len() - 1], 1024 * 1024),
+        'G' => (&text[..text.len() - 1], 1024 * 1024 * 1024),
+        _ => (text, 1),
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    if number < 0.0 {
+        return None;
+    }
+    Some((number * multiple as f64) as u64)
+}
+
+/// 临时文件夹，离开作用域时删除
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    /// 在系统临时目录中创建，文件夹名包含进程号和序号，同一进程中多次创建也不会冲突
+    pub fn new(name: &str) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "cr-cli-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        std::fs::create_dir_all(&dir)?;
+        Ok(TempDir(dir))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn size_round_trip() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(200 * 1024 * 1024), "200.0 MB");
+        assert_eq!(parse_size(&toml::Value::from(512)), Some(512));
+        assert_eq!(parse_size(&toml::Value::from("200KB")), Some(200 * 1024));
+        assert_eq!(parse_size(&toml::Value::from("1.5 m")), Some(1536 * 1024));
+        assert_eq!(parse_size(&toml::Value::from("B")), None);
+        assert_eq!(parse_size(&toml::Value::from("abc")), None);
+    }
+}
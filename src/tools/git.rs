@@ -0,0 +1,881 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use flate2::read::ZlibDecoder;
+use sha1::{Digest, Sha1};
+
+const INDEX_SIGNATURE: &[u8; 4] = b"DIRC";
+const PACK_INDEX_SIGNATURE: &[u8; 4] = b"\xfftOc";
+/// 普通文件和可执行文件的模式，符号链接和子模块不会被导出
+const FILE_MODES: &[u32] = &[0o100644, 0o100755];
+
+/// git 对象的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl ObjectKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "commit" => Some(ObjectKind::Commit),
+            "tree" => Some(ObjectKind::Tree),
+            "blob" => Some(ObjectKind::Blob),
+            "tag" => Some(ObjectKind::Tag),
+            _ => None,
+        }
+    }
+
+    fn from_pack_type(pack_type: u8) -> Option<Self> {
+        match pack_type {
+            1 => Some(ObjectKind::Commit),
+            2 => Some(ObjectKind::Tree),
+            3 => Some(ObjectKind::Blob),
+            4 => Some(ObjectKind::Tag),
+            _ => None,
+        }
+    }
+}
+
+/// 暂存区(`.git/index`)中的一个文件
+#[derive(Debug, Clone)]
+pub struct IndexEntry {
+    /// 相对于仓库根目录的路径
+    pub path: String,
+    /// blob 对象的 SHA-1
+    pub id: String,
+    pub mode: u32,
+    pub size: u32,
+    /// 合并冲突时不为 0
+    pub stage: u8,
+}
+
+/// 提交中的一个文件
+pub struct TreeFile {
+    /// 相对于仓库根目录的路径
+    pub path: String,
+    /// blob 对象的 SHA-1
+    pub id: String,
+    pub mode: u32,
+}
+
+/// 未提交的修改
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// 已暂存 HEAD 中没有的文件
+    Added(String),
+    /// 已暂存或者工作区中修改的文件
+    Modified(String),
+    /// 已暂存或者工作区中删除的文件
+    Deleted(String),
+    /// 存在合并冲突的文件
+    Conflicted(String),
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added(path) => write!(f, "新增: {}", path),
+            Change::Modified(path) => write!(f, "修改: {}", path),
+            Change::Deleted(path) => write!(f, "删除: {}", path),
+            Change::Conflicted(path) => write!(f, "冲突: {}", path),
+        }
+    }
+}
+
+/// 直接读取 `.git` 文件夹的仓库，不依赖 git 命令
+pub struct Repository {
+    /// 工作区根目录
+    pub work_dir: PathBuf,
+    /// `.git` 文件夹，工作树(worktree)中为 `.git/worktrees/<name>`
+    git_dir: PathBuf,
+    /// 存放对象和引用的文件夹，普通仓库中与 `git_dir` 相同
+    common_dir: PathBuf,
+}
+
+impl Repository {
+    /// 从路径本身或者它的上级中查找仓库
+    pub fn discover(path: &Path) -> Option<Repository> {
+        for dir in path.ancestors() {
+            let dot_git = dir.join(".git");
+            let git_dir = if dot_git.is_dir() {
+                dot_git
+            } else if dot_git.is_file() {
+                // 工作树和子模块中的 `.git` 是记录真正位置的文件
+                let content = std::fs::read_to_string(&dot_git).ok()?;
+                let git_dir = content.trim().strip_prefix("gitdir:")?.trim();
+                dir.join(git_dir)
+            } else {
+                continue;
+            };
+            let common_dir = match std::fs::read_to_string(git_dir.join("commondir")) {
+                Ok(common_dir) => git_dir.join(common_dir.trim()),
+                Err(_) => git_dir.clone(),
+            };
+            return Some(Repository {
+                work_dir: dir.to_path_buf(),
+                git_dir,
+                common_dir,
+            });
+        }
+        None
+    }
+
+    /// HEAD 指向的提交，还没有提交时返回 `None`
+    pub fn head(&self) -> Result<Option<String>, Box<dyn Error>> {
+        let head = std::fs::read_to_string(self.git_dir.join("HEAD"))?;
+        let head = head.trim();
+        let Some(reference) = head.strip_prefix("ref:") else {
+            return Ok(Some(head.to_string()));
+        };
+        self.resolve_ref(reference.trim())
+    }
+
+    fn resolve_ref(&self, reference: &str) -> Result<Option<String>, Box<dyn Error>> {
+        for dir in [&self.git_dir, &self.common_dir] {
+            if let Ok(id) = std::fs::read_to_string(dir.join(reference)) {
+                return Ok(Some(id.trim().to_string()));
+            }
+        }
+        let Ok(packed_refs) = std::fs::read_to_string(self.common_dir.join("packed-refs")) else {
+            return Ok(None);
+        };
+        Ok(packed_refs
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+            .filter_map(|line| line.split_once(' '))
+            .find(|(_, name)| *name == reference)
+            .map(|(id, _)| id.to_string()))
+    }
+
+    /// 读取暂存区中的全部文件
+    pub fn index(&self) -> Result<Vec<IndexEntry>, Box<dyn Error>> {
+        let data = match std::fs::read(self.git_dir.join("index")) {
+            Ok(data) => data,
+            // 新建的仓库还没有暂存区
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        parse_index(&data)
+    }
+
+    /// 提交中的全部文件，返回路径、blob 的 SHA-1 和模式
+    pub fn commit_files(&self, commit: &str) -> Result<Vec<TreeFile>, Box<dyn Error>> {
+        let (kind, data) = self.read_object(commit)?;
+        if kind != ObjectKind::Commit {
+            return Err(invalid_data(&format!("{} 不是一个提交", commit)).into());
+        }
+        let tree = String::from_utf8_lossy(&data)
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("tree "))
+            .ok_or_else(|| invalid_data("提交中缺少 tree"))?
+            .to_string();
+        let mut files = Vec::new();
+        self.walk_tree(&tree, "", &mut files)?;
+        Ok(files)
+    }
+
+    fn walk_tree(
+        &self,
+        tree: &str,
+        prefix: &str,
+        files: &mut Vec<TreeFile>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (kind, data) = self.read_object(tree)?;
+        if kind != ObjectKind::Tree {
+            return Err(invalid_data(&format!("{} 不是一个目录树", tree)).into());
+        }
+        let mut rest = data.as_slice();
+        while !rest.is_empty() {
+            let space = rest
+                .iter()
+                .position(|b| *b == b' ')
+                .ok_or_else(|| invalid_data("目录树损坏"))?;
+            let nul = rest
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or_else(|| invalid_data("目录树损坏"))?;
+            let mode = u32::from_str_radix(std::str::from_utf8(&rest[..space])?, 8)?;
+            let name = String::from_utf8_lossy(&rest[space + 1..nul]);
+            let id = rest
+                .get(nul + 1..nul + 21)
+                .ok_or_else(|| invalid_data("目录树损坏"))?;
+            let path = format!("{}{}", prefix, name);
+            if mode == 0o40000 {
+                self.walk_tree(&to_hex(id), &format!("{}/", path), files)?;
+            } else {
+                files.push(TreeFile {
+                    path,
+                    id: to_hex(id),
+                    mode,
+                });
+            }
+            rest = &rest[nul + 21..];
+        }
+        Ok(())
+    }
+
+    /// 读取松散对象或者打包文件中的对象
+    pub fn read_object(&self, id: &str) -> Result<(ObjectKind, Vec<u8>), Box<dyn Error>> {
+        let objects = self.common_dir.join("objects");
+        if id.len() == 40 {
+            let loose = objects.join(&id[..2]).join(&id[2..]);
+            if let Ok(file) = File::open(&loose) {
+                let mut data = Vec::new();
+                ZlibDecoder::new(file).read_to_end(&mut data)?;
+                let nul = data
+                    .iter()
+                    .position(|b| *b == 0)
+                    .ok_or_else(|| invalid_data("对象损坏"))?;
+                let header = String::from_utf8_lossy(&data[..nul]).to_string();
+                let kind = header
+                    .split(' ')
+                    .next()
+                    .and_then(ObjectKind::from_name)
+                    .ok_or_else(|| invalid_data("未知的对象类型"))?;
+                return Ok((kind, data[nul + 1..].to_vec()));
+            }
+        }
+        let raw_id = from_hex(id).ok_or_else(|| invalid_data(&format!("无效的对象 {}", id)))?;
+        if let Ok(read_dir) = std::fs::read_dir(objects.join("pack")) {
+            for entry in read_dir.filter_map(|entry| entry.ok()) {
+                let idx = entry.path();
+                if idx.extension().is_none_or(|ext| ext != "idx") {
+                    continue;
+                }
+                if let Some(offset) = find_in_pack_index(&idx, &raw_id)? {
+                    return self.read_packed(&idx.with_extension("pack"), offset);
+                }
+            }
+        }
+        Err(invalid_data(&format!("找不到对象 {}", id)).into())
+    }
+
+    /// 读取打包文件中指定位置的对象，并还原增量压缩的对象
+    fn read_packed(
+        &self,
+        pack: &Path,
+        offset: u64,
+    ) -> Result<(ObjectKind, Vec<u8>), Box<dyn Error>> {
+        let mut file = File::open(pack)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut byte = [0u8; 1];
+        file.read_exact(&mut byte)?;
+        let pack_type = (byte[0] >> 4) & 7;
+        while byte[0] & 0x80 != 0 {
+            file.read_exact(&mut byte)?;
+        }
+        let base = match pack_type {
+            // OFS_DELTA 基础对象在同一个打包文件中
+            6 => {
+                file.read_exact(&mut byte)?;
+                let mut distance = (byte[0] & 0x7f) as u64;
+                while byte[0] & 0x80 != 0 {
+                    file.read_exact(&mut byte)?;
+                    distance = distance
+                        .checked_add(1)
+                        .and_then(|distance| distance.checked_mul(1 << 7))
+                        .ok_or_else(|| invalid_data("打包文件损坏"))?
+                        | (byte[0] & 0x7f) as u64;
+                }
+                // 基础对象一定在当前对象之前
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .filter(|_| distance != 0)
+                    .ok_or_else(|| invalid_data("打包文件损坏"))?;
+                Some(self.read_packed(pack, base_offset)?)
+            }
+            // REF_DELTA 基础对象可能在任何位置
+            7 => {
+                let mut base_id = [0u8; 20];
+                file.read_exact(&mut base_id)?;
+                Some(self.read_object(&to_hex(&base_id))?)
+            }
+            _ => None,
+        };
+        let mut data = Vec::new();
+        ZlibDecoder::new(file).read_to_end(&mut data)?;
+        match base {
+            Some((kind, base)) => Ok((kind, apply_delta(&base, &data)?)),
+            None => {
+                let kind = ObjectKind::from_pack_type(pack_type)
+                    .ok_or_else(|| invalid_data("未知的对象类型"))?;
+                Ok((kind, data))
+            }
+        }
+    }
+
+    /// 比较 HEAD、暂存区和工作区，返回 `prefix` 文件夹中未提交的修改
+    pub fn changes(&self, prefix: &str) -> Result<Vec<Change>, Box<dyn Error>> {
+        let head_files = match self.head()? {
+            Some(commit) => self.commit_files(&commit)?,
+            None => Vec::new(),
+        };
+        let mut head_files = head_files
+            .into_iter()
+            .filter(|file| in_prefix(&file.path, prefix))
+            .map(|file| (file.path, file.id))
+            .collect::<BTreeMap<String, String>>();
+        let mut changes = Vec::new();
+        for entry in self.index()? {
+            if !in_prefix(&entry.path, prefix) {
+                continue;
+            }
+            let head_id = head_files.remove(&entry.path);
+            if entry.stage != 0 {
+                if !changes.contains(&Change::Conflicted(entry.path.clone())) {
+                    changes.push(Change::Conflicted(entry.path));
+                }
+                continue;
+            }
+            // 已暂存的修改
+            let staged = match head_id {
+                None => Some(Change::Added(entry.path.clone())),
+                Some(head_id) if head_id != entry.id => Some(Change::Modified(entry.path.clone())),
+                _ => None,
+            };
+            let path = self.work_dir.join(&entry.path);
+            if FILE_MODES.contains(&entry.mode) && !path.is_file() {
+                changes.push(Change::Deleted(entry.path));
+                continue;
+            }
+            if let Some(staged) = staged {
+                changes.push(staged);
+                continue;
+            }
+            // 工作区中的修改 大小相同时再比较内容
+            // 写入暂存区时刚修改过的文件(racily clean)大小会被 git 记为 0 这时只能比较内容
+            if FILE_MODES.contains(&entry.mode)
+                && ((entry.size != 0 && path.metadata()?.len() != entry.size as u64)
+                    || !same_content(&std::fs::read(&path)?, &entry.id))
+            {
+                changes.push(Change::Modified(entry.path));
+            }
+        }
+        // 已经从暂存区中删除的文件
+        changes.extend(head_files.into_keys().map(Change::Deleted));
+        Ok(changes)
+    }
+
+    /// 把提交中 `prefix` 文件夹里的文件导出到 `target`，返回导出的文件数量
+    pub fn export(
+        &self,
+        commit: &str,
+        prefix: &str,
+        target: &Path,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut count = 0;
+        for TreeFile { path, id, mode } in self.commit_files(commit)? {
+            if !in_prefix(&path, prefix) || !FILE_MODES.contains(&mode) {
+                continue;
+            }
+            let relative = path[prefix.len()..].trim_start_matches('/');
+            let (_, data) = self.read_object(&id)?;
+            let file = target.join(relative);
+            std::fs::create_dir_all(file.parent().unwrap())?;
+            std::fs::write(file, data)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// 路径在 `prefix` 文件夹中，`prefix` 为空时表示仓库根目录
+pub fn in_prefix(path: &str, prefix: &str) -> bool {
+    prefix.is_empty()
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// 内容作为 blob 对象时的 SHA-1
+pub fn blob_id(data: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", data.len()).as_bytes());
+    hasher.update(data);
+    to_hex(&hasher.finalize())
+}
+
+/// 工作区中的内容与 blob 对象是否相同
+/// `core.autocrlf` 或者 `.gitattributes` 中 `eol=crlf` 的文本文件检出后是 CRLF，对象中是 LF，
+/// 所以内容不同时把文本文件的 CRLF 换成 LF 再比较一次
+fn same_content(data: &[u8], id: &str) -> bool {
+    if blob_id(data) == id {
+        return true;
+    }
+    // 与 git 相同 前 8000 个字节中有 NUL 时是二进制文件 不转换换行符
+    let is_binary = data.iter().take(8000).any(|b| *b == 0);
+    if is_binary || !data.contains(&b'\r') {
+        return false;
+    }
+    let mut normalized = Vec::with_capacity(data.len());
+    for (i, b) in data.iter().enumerate() {
+        if *b == b'\r' && data.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        normalized.push(*b);
+    }
+    blob_id(&normalized) == id
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut bytes = [0u8; 20];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, io::Error> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid_data("文件不完整"))
+}
+
+/// 解析暂存区文件，支持第 2、3、4 版
+fn parse_index(data: &[u8]) -> Result<Vec<IndexEntry>, Box<dyn Error>> {
+    if data.get(..4) != Some(INDEX_SIGNATURE) {
+        return Err(invalid_data("不是 git 暂存区文件").into());
+    }
+    let version = read_u32(data, 4)?;
+    if !(2..=4).contains(&version) {
+        return Err(invalid_data(&format!("不支持第 {} 版的暂存区文件", version)).into());
+    }
+    let count = read_u32(data, 8)?;
+    let mut entries = Vec::new();
+    let mut offset = 12;
+    let mut previous_name: Vec<u8> = Vec::new();
+    for _ in 0..count {
+        let start = offset;
+        let mode = read_u32(data, start + 24)?;
+        let size = read_u32(data, start + 36)?;
+        let id = data
+            .get(start + 40..start + 60)
+            .ok_or_else(|| invalid_data("暂存区文件不完整"))?;
+        let flags = data
+            .get(start + 60..start + 62)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| invalid_data("暂存区文件不完整"))?;
+        offset = start + 62;
+        // 扩展标记
+        if version >= 3 && flags & 0x4000 != 0 {
+            offset += 2;
+        }
+        let name = if version == 4 {
+            // 第 4 版的路径省略了与上一个路径相同的前缀
+            let mut byte = *data
+                .get(offset)
+                .ok_or_else(|| invalid_data("暂存区文件不完整"))?;
+            offset += 1;
+            let mut strip = (byte & 0x7f) as usize;
+            while byte & 0x80 != 0 {
+                byte = *data
+                    .get(offset)
+                    .ok_or_else(|| invalid_data("暂存区文件不完整"))?;
+                offset += 1;
+                strip = ((strip + 1) << 7) | (byte & 0x7f) as usize;
+            }
+            let nul = data
+                .get(offset..)
+                .and_then(|rest| rest.iter().position(|b| *b == 0))
+                .ok_or_else(|| invalid_data("暂存区文件不完整"))?;
+            let keep = previous_name.len().saturating_sub(strip);
+            let mut name = previous_name[..keep].to_vec();
+            name.extend_from_slice(&data[offset..offset + nul]);
+            offset += nul + 1;
+            name
+        } else {
+            let nul = data
+                .get(offset..)
+                .and_then(|rest| rest.iter().position(|b| *b == 0))
+                .ok_or_else(|| invalid_data("暂存区文件不完整"))?;
+            let name = data[offset..offset + nul].to_vec();
+            // 条目长度补齐到 8 的倍数 至少有一个 NUL
+            offset = start + (offset - start + nul + 8) / 8 * 8;
+            name
+        };
+        entries.push(IndexEntry {
+            path: String::from_utf8_lossy(&name).to_string(),
+            id: to_hex(id),
+            mode,
+            size,
+            stage: ((flags >> 12) & 3) as u8,
+        });
+        previous_name = name;
+    }
+    Ok(entries)
+}
+
+/// 在打包文件的索引(第 2 版)中查找对象，返回对象在打包文件中的位置
+fn find_in_pack_index(idx: &Path, id: &[u8; 20]) -> Result<Option<u64>, Box<dyn Error>> {
+    let data = std::fs::read(idx)?;
+    if data.get(..4) != Some(PACK_INDEX_SIGNATURE) || read_u32(&data, 4)? != 2 {
+        return Err(invalid_data("不支持的打包索引格式").into());
+    }
+    let fanout = |i: usize| read_u32(&data, 8 + i * 4).map(|n| n as usize);
+    let total = fanout(255)?;
+    let mut low = match id[0] {
+        0 => 0,
+        first => fanout(first as usize - 1)?,
+    };
+    let mut high = fanout(id[0] as usize)?;
+    let ids_start = 8 + 256 * 4;
+    while low < high {
+        let middle = (low + high) / 2;
+        let start = ids_start + middle * 20;
+        let candidate = data
+            .get(start..start + 20)
+            .ok_or_else(|| invalid_data("打包索引不完整"))?;
+        match candidate.cmp(id.as_slice()) {
+            std::cmp::Ordering::Less => low = middle + 1,
+            std::cmp::Ordering::Greater => high = middle,
+            std::cmp::Ordering::Equal => {
+                // SHA-1 列表之后是 CRC32 列表 然后是 32 位的位置
+                let offsets_start = ids_start + total * 24;
+                let offset = read_u32(&data, offsets_start + middle * 4)?;
+                if offset & 0x8000_0000 == 0 {
+                    return Ok(Some(offset as u64));
+                }
+                // 最高位为 1 时是 64 位位置表中的下标
+                let large_start = offsets_start + total * 4 + (offset & 0x7fff_ffff) as usize * 8;
+                let high_bits = read_u32(&data, large_start)? as u64;
+                let low_bits = read_u32(&data, large_start + 4)? as u64;
+                return Ok(Some((high_bits << 32) | low_bits));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// 还原增量压缩的对象
+fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut position = 0;
+    let base_size = read_delta_size(delta, &mut position)?;
+    if base_size != base.len() {
+        return Err(invalid_data("增量数据与基础对象不匹配"));
+    }
+    let result_size = read_delta_size(delta, &mut position)?;
+    let mut result = Vec::with_capacity(result_size);
+    while result.len() < result_size {
+        let instruction = read_delta_byte(delta, &mut position)?;
+        if instruction & 0x80 != 0 {
+            // 从基础对象中复制 低 4 位表示位置的字节 之后 3 位表示大小的字节
+            let mut copy_offset = 0usize;
+            for i in 0..4 {
+                if instruction & (1 << i) != 0 {
+                    copy_offset |= (read_delta_byte(delta, &mut position)? as usize) << (i * 8);
+                }
+            }
+            let mut copy_size = 0usize;
+            for i in 0..3 {
+                if instruction & (0x10 << i) != 0 {
+                    copy_size |= (read_delta_byte(delta, &mut position)? as usize) << (i * 8);
+                }
+            }
+            if copy_size == 0 {
+                copy_size = 0x10000;
+            }
+            let copied = base
+                .get(copy_offset..copy_offset + copy_size)
+                .ok_or_else(|| invalid_data("增量数据超出基础对象"))?;
+            result.extend_from_slice(copied);
+        } else if instruction != 0 {
+            // 插入之后的 instruction 个字节
+            let inserted = delta
+                .get(position..position + instruction as usize)
+                .ok_or_else(|| invalid_data("增量数据不完整"))?;
+            result.extend_from_slice(inserted);
+            position += instruction as usize;
+        } else {
+            return Err(invalid_data("无效的增量指令"));
+        }
+    }
+    Ok(result)
+}
+
+fn read_delta_byte(delta: &[u8], position: &mut usize) -> Result<u8, io::Error> {
+    let byte = *delta
+        .get(*position)
+        .ok_or_else(|| invalid_data("增量数据不完整"))?;
+    *position += 1;
+    Ok(byte)
+}
+
+/// 增量数据开头的对象大小 每个字节 7 位 低位在前
+fn read_delta_size(delta: &[u8], position: &mut usize) -> Result<usize, io::Error> {
+    let mut size = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = read_delta_byte(delta, position)?;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tools::files::TempDir;
+
+    #[test]
+    fn apply_copy_and_insert_delta() {
+        let base = b"hello world";
+        // 基础大小 11 结果大小 13: 复制 "hello " 插入 "there" 复制 "ld"
+        let delta = [
+            11, 13, 0x91, 0, 6, 5, b't', b'h', b'e', b'r', b'e', 0x91, 9, 2,
+        ];
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"hello thereld");
+        assert!(apply_delta(b"short", &delta).is_err());
+    }
+
+    #[test]
+    fn blob_id_matches_git() {
+        // git hash-object 对空文件和 "hello\n" 的结果
+        assert_eq!(blob_id(b""), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+        assert_eq!(
+            blob_id(b"hello\n"),
+            "ce013625030ba8dba906f756967f9e9ca394464a"
+        );
+        assert!(in_prefix("src/main.cpp", "src"));
+        assert!(!in_prefix("src2/main.cpp", "src"));
+        assert!(in_prefix("main.cpp", ""));
+    }
+
+    /// 在临时仓库中执行 git 命令，不读取用户和系统的 git 配置，没有安装 git 时返回 `None`
+    fn git(dir: &Path, args: &[&str]) -> Option<Vec<u8>> {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", dir.join(".no-gitconfig"))
+            .env("GIT_AUTHOR_NAME", "张三")
+            .env("GIT_AUTHOR_EMAIL", "me@example.com")
+            .env("GIT_COMMITTER_NAME", "张三")
+            .env("GIT_COMMITTER_EMAIL", "me@example.com")
+            .output()
+            .ok()?;
+        assert!(
+            output.status.success(),
+            "git {:?}: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Some(output.stdout)
+    }
+
+    /// 两次提交的测试仓库，main.cpp 在两次提交之间只改了一行，打包时会生成增量对象
+    /// 没有安装 git 时跳过测试
+    fn fixture(name: &str) -> Option<(TempDir, PathBuf)> {
+        let temp = TempDir::new(name).unwrap();
+        let dir = temp.0.join("repo");
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        if git(&dir, &["init", "-q"]).is_none() {
+            eprintln!("没有安装 git，跳过测试");
+            return None;
+        }
+        let lines = (0..300)
+            .map(|i| format!("int line_{} = {};\n", i, i))
+            .collect::<String>();
+        std::fs::write(dir.join("src/main.cpp"), &lines).unwrap();
+        std::fs::write(dir.join("README.md"), "hello\n").unwrap();
+        git(&dir, &["add", "-A"])?;
+        git(&dir, &["commit", "-q", "-m", "first"])?;
+        let lines = lines.replace("int line_150 = 150;", "int line_150 = -1;");
+        std::fs::write(dir.join("src/main.cpp"), &lines).unwrap();
+        std::fs::write(dir.join("src/util.h"), "#pragma once\n").unwrap();
+        git(&dir, &["add", "-A"])?;
+        git(&dir, &["commit", "-q", "-m", "second"])?;
+        Some((temp, dir))
+    }
+
+    /// 逐个比较仓库中全部对象与 `git cat-file` 的结果
+    fn assert_objects_match_git(repo: &Repository, dir: &Path) {
+        let objects = git(dir, &["rev-list", "--objects", "--all"]).unwrap();
+        let objects = String::from_utf8(objects).unwrap();
+        assert!(!objects.is_empty());
+        for line in objects.lines() {
+            let id = &line[..40];
+            let kind = git(dir, &["cat-file", "-t", id]).unwrap();
+            let kind = String::from_utf8(kind).unwrap();
+            let expected = git(dir, &["cat-file", kind.trim(), id]).unwrap();
+            let (actual_kind, actual) = repo.read_object(id).unwrap();
+            assert_eq!(Some(actual_kind), ObjectKind::from_name(kind.trim()));
+            assert_eq!(actual, expected, "对象 {}", id);
+        }
+    }
+
+    /// 打包文件中增量对象的类型，6 为 OFS_DELTA，7 为 REF_DELTA
+    fn delta_types(dir: &Path) -> Vec<u8> {
+        let pack_dir = dir.join(".git/objects/pack");
+        let mut types = Vec::new();
+        for entry in std::fs::read_dir(&pack_dir).unwrap() {
+            let idx = entry.unwrap().path();
+            if idx.extension().is_none_or(|ext| ext != "idx") {
+                continue;
+            }
+            let pack = std::fs::read(idx.with_extension("pack")).unwrap();
+            let output = git(dir, &["verify-pack", "-v", idx.to_str().unwrap()]).unwrap();
+            // 增量对象的一行为: SHA-1 类型 大小 打包后的大小 位置 深度 基础对象
+            for line in String::from_utf8(output).unwrap().lines() {
+                let fields = line.split_whitespace().collect::<Vec<&str>>();
+                if fields.len() == 7 {
+                    let offset = fields[4].parse::<usize>().unwrap();
+                    types.push((pack[offset] >> 4) & 7);
+                }
+            }
+        }
+        types
+    }
+
+    #[test]
+    fn read_loose_and_packed_objects() {
+        let Some((temp, dir)) = fixture("git-objects") else {
+            return;
+        };
+        let repo = Repository::discover(&dir.join("src")).unwrap();
+        assert_eq!(repo.work_dir, dir);
+        let head = repo.head().unwrap().unwrap();
+        // 松散对象
+        assert!(!dir
+            .join(".git/objects/pack")
+            .read_dir()
+            .unwrap()
+            .any(|_| true));
+        assert_objects_match_git(&repo, &dir);
+
+        // 打包文件 默认使用 OFS_DELTA
+        git(&dir, &["repack", "-a", "-d", "-f", "-q"]).unwrap();
+        git(&dir, &["prune-packed"]).unwrap();
+        assert!(delta_types(&dir).contains(&6));
+        assert_objects_match_git(&repo, &dir);
+
+        // 基础对象使用 SHA-1 表示的 REF_DELTA
+        git(
+            &dir,
+            &[
+                "-c",
+                "repack.useDeltaBaseOffset=false",
+                "repack",
+                "-a",
+                "-d",
+                "-f",
+                "-q",
+            ],
+        )
+        .unwrap();
+        let types = delta_types(&dir);
+        assert!(!types.is_empty() && types.iter().all(|t| *t == 7));
+        assert_objects_match_git(&repo, &dir);
+
+        let target = temp.0.join("export");
+        assert_eq!(repo.export(&head, "src", &target).unwrap(), 2);
+        assert_eq!(
+            std::fs::read(target.join("main.cpp")).unwrap(),
+            std::fs::read(dir.join("src/main.cpp")).unwrap()
+        );
+        assert_eq!(
+            std::fs::read_to_string(target.join("util.h")).unwrap(),
+            "#pragma once\n"
+        );
+        assert!(!target.join("README.md").exists());
+
+        // 增量对象的基础对象位置在打包文件之前 返回错误而不是溢出
+        let pack = temp.0.join("broken.pack");
+        std::fs::write(&pack, [0x60, 0x05]).unwrap();
+        assert!(repo.read_packed(&pack, 0).is_err());
+    }
+
+    #[test]
+    fn parse_index_and_detect_changes() {
+        let Some((_temp, dir)) = fixture("git-index") else {
+            return;
+        };
+        let repo = Repository::discover(&dir).unwrap();
+        let summary = |entries: Vec<IndexEntry>| {
+            entries
+                .into_iter()
+                .map(|entry| (entry.path, entry.id, entry.mode, entry.size))
+                .collect::<Vec<_>>()
+        };
+        let index_version =
+            || read_u32(&std::fs::read(dir.join(".git/index")).unwrap(), 4).unwrap();
+        assert_eq!(index_version(), 2);
+        let v2 = summary(repo.index().unwrap());
+        let paths = v2
+            .iter()
+            .map(|entry| entry.0.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(paths, ["README.md", "src/main.cpp", "src/util.h"]);
+        assert_eq!(v2[0].1, blob_id(b"hello\n"));
+
+        // 第 4 版的路径压缩
+        git(&dir, &["update-index", "--index-version", "4"]).unwrap();
+        assert_eq!(index_version(), 4);
+        assert_eq!(summary(repo.index().unwrap()), v2);
+
+        // 不完整的暂存区文件返回错误而不是崩溃
+        let data = std::fs::read(dir.join(".git/index")).unwrap();
+        for len in 0..data.len() {
+            let _ = parse_index(&data[..len]);
+        }
+        assert!(parse_index(&data[..40]).is_err());
+
+        assert!(repo.changes("").unwrap().is_empty());
+        std::fs::write(dir.join("README.md"), "hello world\n").unwrap();
+        std::fs::write(dir.join("src/new.cpp"), "int main() {}\n").unwrap();
+        git(&dir, &["add", "src/new.cpp"]).unwrap();
+        std::fs::remove_file(dir.join("src/util.h")).unwrap();
+        assert_eq!(
+            repo.changes("").unwrap(),
+            [
+                Change::Modified("README.md".to_string()),
+                Change::Added("src/new.cpp".to_string()),
+                Change::Deleted("src/util.h".to_string()),
+            ]
+        );
+        assert_eq!(repo.changes("src").unwrap().len(), 2);
+
+        // core.autocrlf 时工作区中是 CRLF 对象中是 LF 不算修改
+        git(&dir, &["checkout", "-q", "--", "."]).unwrap();
+        git(&dir, &["reset", "-q", "--hard"]).unwrap();
+        std::fs::write(dir.join("crlf.txt"), "a\r\nb\r\n").unwrap();
+        git(&dir, &["-c", "core.autocrlf=true", "add", "crlf.txt"]).unwrap();
+        git(&dir, &["commit", "-q", "-m", "crlf"]).unwrap();
+        let entry = repo
+            .index()
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.path == "crlf.txt")
+            .unwrap();
+        assert_eq!(entry.id, blob_id(b"a\nb\n"));
+        assert!(repo.changes("").unwrap().is_empty());
+        std::fs::write(dir.join("crlf.txt"), "a\r\nc\r\n").unwrap();
+        assert_eq!(
+            repo.changes("").unwrap(),
+            [Change::Modified("crlf.txt".to_string())]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use glob::Pattern;
+use toml::Table;
+
+use super::files::{format_size, parse_size};
+
+/// 默认的单个文件大小警告阈值
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// 默认视为可执行文件或目标文件的扩展名
+const DEFAULT_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "so", "dylib", "obj", "o", "lib", "a", "pdb", "ilk", "pch", "exp", "idb", "class",
+];
+
+/// 默认视为IDE缓存的文件或文件夹名称
+const DEFAULT_CACHES: &[&str] = &[
+    ".vs",
+    "ipch",
+    ".idea",
+    "*.ipch",
+    "*.sdf",
+    "*.VC.db",
+    "*.VC.opendb",
+    "*.ncb",
+    "*.suo",
+    "*.aps",
+];
+
+/// 压缩前需要提醒的文件
+pub enum GuardIssue {
+    /// 文件大小超过 `max_file_size`
+    Oversized(u64),
+    /// 文件头是可执行文件的格式
+    Executable,
+    /// 扩展名在 `extensions` 中
+    Extension(String),
+    /// 名称匹配 `caches` 中的规则
+    IdeCache(String),
+}
+
+impl std::fmt::Display for GuardIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GuardIssue::Oversized(size) => write!(f, "文件过大: {}", format_size(*size)),
+            GuardIssue::Executable => write!(f, "可执行文件"),
+            GuardIssue::Extension(ext) => write!(f, "可执行文件或目标文件: .{}", ext),
+            GuardIssue::IdeCache(pattern) => write!(f, "IDE缓存: {}", pattern),
+        }
+    }
+}
+
+/// `[zip.guard]` 中配置的检查规则
+pub struct GuardRules {
+    pub enabled: bool,
+    pub strict: bool,
+    max_file_size: u64,
+    extensions: Vec<String>,
+    caches: Vec<Pattern>,
+}
+
+impl GuardRules {
+    /// 由 `[zip.guard]` 表生成规则，未配置的字段使用默认值
+    pub fn from_table(table: Option<&Table>) -> Result<Self, String> {
+        let empty = Table::new();
+        let table = table.unwrap_or(&empty);
+        let get_bool = |key: &str| table.get(key).and_then(|v| v.as_bool());
+        let get_list = |key: &str, default: &[&str]| match table.get(key) {
+            Some(list) => list
+                .as_array()
+                .ok_or(format!("[zip.guard] 中的 {} 必须是字符串列表", key))?
+                .iter()
+                .map(|v| {
+                    v.as_str()
+                        .map(|s| s.to_string())
+                        .ok_or(format!("[zip.guard] 中的 {} 必须是字符串列表", key))
+                })
+                .collect::<Result<Vec<String>, String>>(),
+            None => Ok(default.iter().map(|s| s.to_string()).collect()),
+        };
+        let max_file_size = match table.get("max_file_size") {
+            Some(value) => {
+                parse_size(value).ok_or(format!("[zip.guard] 中无效的 max_file_size: {}", value))?
+            }
+            None => DEFAULT_MAX_FILE_SIZE,
+        };
+        let caches = get_list("caches", DEFAULT_CACHES)?
+            .iter()
+            .map(|pattern| {
+                Pattern::new(pattern).map_err(|err| format!("无效的匹配规则 {}: {}", pattern, err))
+            })
+            .collect::<Result<Vec<Pattern>, String>>()?;
+        Ok(GuardRules {
+            enabled: get_bool("enabled").unwrap_or(true),
+            strict: get_bool("strict").unwrap_or(false),
+            max_file_size,
+            extensions: get_list("extensions", DEFAULT_EXTENSIONS)?
+                .into_iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect(),
+            caches,
+        })
+    }
+
+    /// 检查一个将要压缩的文件或文件夹
+    pub fn check(&self, source: &Path, is_dir: bool, size: u64) -> Option<GuardIssue> {
+        let file_name = source.file_name()?.to_string_lossy();
+        if let Some(pattern) = self.caches.iter().find(|p| p.matches(&file_name)) {
+            return Some(GuardIssue::IdeCache(pattern.as_str().to_string()));
+        }
+        if is_dir {
+            return None;
+        }
+        if let Some(ext) = source.extension() {
+            let ext = ext.to_string_lossy().to_lowercase();
+            if self.extensions.contains(&ext) {
+                return Some(GuardIssue::Extension(ext));
+            }
+        }
+        if is_executable(source) {
+            return Some(GuardIssue::Executable);
+        }
+        if size > self.max_file_size {
+            return Some(GuardIssue::Oversized(size));
+        }
+        None
+    }
+}
+
+/// 通过文件头判断是否是 PE/ELF/Mach-O 格式的可执行文件
+fn is_executable(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return false;
+    };
+    if file.read_exact(&mut magic).is_err() {
+        return false;
+    }
+    if magic.starts_with(b"MZ") {
+        return is_pe(&mut file);
+    }
+    magic == *b"\x7fELF"
+        || [0xfeedface_u32, 0xfeedfacf, 0xcafebabe]
+            .iter()
+            .any(|m| magic == m.to_be_bytes() || magic == m.to_le_bytes())
+}
+
+/// 以 `MZ` 开头的文件在 0x3c 处记录 PE 头的位置(e_lfanew)，PE 头以 `PE\0\0` 开头
+/// 只检查 `MZ` 时普通的文本文件也会被误认为可执行文件
+fn is_pe(file: &mut std::fs::File) -> bool {
+    let mut e_lfanew = [0u8; 4];
+    let mut signature = [0u8; 4];
+    file.seek(SeekFrom::Start(0x3c)).is_ok()
+        && file.read_exact(&mut e_lfanew).is_ok()
+        && file
+            .seek(SeekFrom::Start(u32::from_le_bytes(e_lfanew) as u64))
+            .is_ok()
+        && file.read_exact(&mut signature).is_ok()
+        && signature == *b"PE\0\0"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tools::files::TempDir;
+
+    #[test]
+    fn detect_guard_issues() {
+        let temp = TempDir::new("guard").unwrap();
+        let rules = GuardRules::from_table(None).unwrap();
+        let check = |name: &str, content: &[u8]| {
+            let path = temp.0.join(name);
+            std::fs::write(&path, content).unwrap();
+            rules.check(&path, false, content.len() as u64)
+        };
+        assert!(check("main.cpp", b"int main() {}\n").is_none());
+        assert!(matches!(
+            check("main.OBJ", b""),
+            Some(GuardIssue::Extension(ext)) if ext == "obj"
+        ));
+        assert!(matches!(
+            check("main.suo", b""),
+            Some(GuardIssue::IdeCache(pattern)) if pattern == "*.suo"
+        ));
+        assert!(matches!(
+            rules.check(&temp.0.join(".vs"), true, 0),
+            Some(GuardIssue::IdeCache(_))
+        ));
+        assert!(rules.check(&temp.0.join("src"), true, 0).is_none());
+        let path = temp.0.join("video.mp4");
+        assert!(matches!(
+            rules.check(&path, false, DEFAULT_MAX_FILE_SIZE + 1),
+            Some(GuardIssue::Oversized(_))
+        ));
+
+        // 没有扩展名的可执行文件通过文件头识别
+        let mut pe = vec![0u8; 0x48];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c] = 0x40;
+        pe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        assert!(matches!(check("main", &pe), Some(GuardIssue::Executable)));
+        assert!(matches!(
+            check("a.out", b"\x7fELF\x02\x01\x01"),
+            Some(GuardIssue::Executable)
+        ));
+        // 以 MZ 开头的文本文件不是可执行文件
+        assert!(check("notes.txt", b"MZ is short for Mark Zbikowski\n").is_none());
+        assert!(check("mz", b"MZ").is_none());
+    }
+
+    #[test]
+    fn custom_guard_rules() {
+        let table = toml::from_str::<Table>(
+            r#"
+            max_file_size = "1KB"
+            extensions = [".Exe"]
+            caches = ["build"]
+            "#,
+        )
+        .unwrap();
+        let rules = GuardRules::from_table(Some(&table)).unwrap();
+        let path = Path::new("build");
+        assert!(matches!(
+            rules.check(path, true, 0),
+            Some(GuardIssue::IdeCache(_))
+        ));
+        assert!(matches!(
+            rules.check(Path::new("a.EXE"), false, 0),
+            Some(GuardIssue::Extension(ext)) if ext == "exe"
+        ));
+        assert!(rules.check(Path::new("a.obj"), false, 0).is_none());
+        assert!(matches!(
+            rules.check(Path::new("a.txt"), false, 2048),
+            Some(GuardIssue::Oversized(2048))
+        ));
+
+        let table = toml::from_str::<Table>(r#"caches = ["["]"#).unwrap();
+        assert!(GuardRules::from_table(Some(&table)).is_err());
+        let table = toml::from_str::<Table>(r#"max_file_size = "big""#).unwrap();
+        assert!(GuardRules::from_table(Some(&table)).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use toml::{Table, Value};
+
+/// 历史记录文件中每一条记录的表名 `[[entry]]`
+const ENTRY_KEY: &str = "entry";
+
+/// 历史记录中的一个附件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryAttachment {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// 一次成功发送的提交记录
+#[derive(Debug, Clone, Default)]
+pub struct HistoryEntry {
+    pub message_id: String,
+    /// RFC 3339 格式的发送成功的时间
+    pub sent_at: String,
+    pub from: String,
+    /// 所有收件人，包括抄送和密送
+    pub recipients: Vec<String>,
+    pub subject: String,
+    pub courses_number: Option<i64>,
+    pub course_name: Option<String>,
+    pub attachments: Vec<HistoryAttachment>,
+}
+
+impl HistoryEntry {
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        table.insert(
+            "message_id".to_string(),
+            Value::from(self.message_id.as_str()),
+        );
+        table.insert("sent_at".to_string(), Value::from(self.sent_at.as_str()));
+        table.insert("from".to_string(), Value::from(self.from.as_str()));
+        table.insert(
+            "recipients".to_string(),
+            Value::Array(
+                self.recipients
+                    .iter()
+                    .map(|recipient| Value::from(recipient.as_str()))
+                    .collect(),
+            ),
+        );
+        table.insert("subject".to_string(), Value::from(self.subject.as_str()));
+        if let Some(courses_number) = self.courses_number {
+            table.insert("courses_number".to_string(), Value::from(courses_number));
+        }
+        if let Some(course_name) = &self.course_name {
+            table.insert("course_name".to_string(), Value::from(course_name.as_str()));
+        }
+        let attachments = self
+            .attachments
+            .iter()
+            .map(|attachment| {
+                let mut table = Table::new();
+                table.insert("name".to_string(), Value::from(attachment.name.as_str()));
+                table.insert("size".to_string(), Value::from(attachment.size as i64));
+                table.insert(
+                    "sha256".to_string(),
+                    Value::from(attachment.sha256.as_str()),
+                );
+                Value::Table(table)
+            })
+            .collect();
+        table.insert("attachments".to_string(), Value::Array(attachments));
+        table
+    }
+
+    pub fn from_table(table: &Table) -> Result<Self, String> {
+        let get_str = |table: &Table, key: &str| {
+            table
+                .get(key)
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        };
+        let message_id = get_str(table, "message_id").ok_or("缺少 message_id")?;
+        let mut attachments = Vec::new();
+        for attachment in table
+            .get("attachments")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let attachment = attachment.as_table().ok_or("attachments 格式错误")?;
+            attachments.push(HistoryAttachment {
+                name: get_str(attachment, "name").ok_or("附件缺少 name")?,
+                size: attachment
+                    .get("size")
+                    .and_then(|v| v.as_integer())
+                    .unwrap_or(0) as u64,
+                sha256: get_str(attachment, "sha256").unwrap_or_default(),
+            });
+        }
+        Ok(HistoryEntry {
+            message_id,
+            sent_at: get_str(table, "sent_at").unwrap_or_default(),
+            from: get_str(table, "from").unwrap_or_default(),
+            recipients: table
+                .get("recipients")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect(),
+            subject: get_str(table, "subject").unwrap_or_default(),
+            courses_number: table.get("courses_number").and_then(|v| v.as_integer()),
+            course_name: get_str(table, "course_name"),
+            attachments,
+        })
+    }
+
+    /// 本地时区的发送时间
+    pub fn sent_time(&self) -> Option<DateTime<Local>> {
+        DateTime::parse_from_rfc3339(&self.sent_at)
+            .ok()
+            .map(|t| t.with_timezone(&Local))
+    }
+
+    /// 附件的内容是否完全相同，不考虑附件的顺序和名称
+    pub fn same_attachments(&self, attachments: &[HistoryAttachment]) -> bool {
+        let hashes = |attachments: &[HistoryAttachment]| {
+            let mut hashes = attachments
+                .iter()
+                .map(|attachment| attachment.sha256.clone())
+                .collect::<Vec<String>>();
+            hashes.sort();
+            hashes
+        };
+        !attachments.is_empty() && hashes(&self.attachments) == hashes(attachments)
+    }
+
+    /// 课程序号和名称，例如 `13-虚函数`
+    pub fn course(&self) -> Option<String> {
+        match (self.courses_number, &self.course_name) {
+            (Some(number), Some(name)) => Some(format!("{}-{}", number, name)),
+            (Some(number), None) => Some(number.to_string()),
+            (None, _) => None,
+        }
+    }
+}
+
+/// 本地的提交历史，每次发送成功后在文件末尾追加一条 `[[entry]]`
+pub struct History {
+    pub path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: &Path) -> Self {
+        History {
+            path: path.to_owned(),
+        }
+    }
+
+    /// 追加一条记录，只在文件末尾写入，不会改写之前的记录
+    pub fn append(&self, entry: &HistoryEntry) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut document = Table::new();
+        document.insert(
+            ENTRY_KEY.to_string(),
+            Value::Array(vec![Value::Table(entry.to_table())]),
+        );
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", toml::to_string(&document).unwrap())
+    }
+
+    /// 全部记录，按发送的先后顺序，历史文件不存在时为空
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>, String> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.to_string()),
+        };
+        let document = toml::from_str::<Table>(&content).map_err(|err| err.to_string())?;
+        document
+            .get(ENTRY_KEY)
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, entry)| {
+                let entry = entry
+                    .as_table()
+                    .ok_or_else(|| format!("第 {} 条记录格式错误", index + 1))?;
+                HistoryEntry::from_table(entry)
+                    .map_err(|err| format!("第 {} 条记录: {}", index + 1, err))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tools::files::TempDir;
+
+    #[test]
+    fn append_and_read_entries() {
+        let temp = TempDir::new("history").unwrap();
+        let history = History::new(&temp.0.join("history.toml"));
+        assert!(history.entries().unwrap().is_empty());
+        let entry = HistoryEntry {
+            message_id: "<abc@host>".to_string(),
+            sent_at: "2024-05-20T21:30:00+08:00".to_string(),
+            from: "me@example.com".to_string(),
+            recipients: vec!["teacher@example.com".to_string()],
+            subject: "CR49_张三_20240520".to_string(),
+            courses_number: Some(13),
+            course_name: Some("虚函数".to_string()),
+            attachments: vec![HistoryAttachment {
+                name: "CR49_张三_20240520.zip".to_string(),
+                size: 1024,
+                sha256: "00".repeat(32),
+            }],
+        };
+        history.append(&entry).unwrap();
+        let second = HistoryEntry {
+            message_id: "<def@host>".to_string(),
+            courses_number: None,
+            course_name: None,
+            attachments: Vec::new(),
+            ..entry.clone()
+        };
+        history.append(&second).unwrap();
+
+        let entries = history.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].message_id, "<abc@host>");
+        assert_eq!(entries[0].course().as_deref(), Some("13-虚函数"));
+        assert_eq!(entries[0].attachments, entry.attachments);
+        assert_eq!(entries[1].course(), None);
+        assert!(entries[1].attachments.is_empty());
+
+        let mut attachments = entry.attachments.clone();
+        assert!(entries[0].same_attachments(&attachments));
+        assert!(!entries[1].same_attachments(&[]));
+        attachments.push(HistoryAttachment {
+            name: "笔记.md".to_string(),
+            size: 10,
+            sha256: "11".repeat(32),
+        });
+        assert!(!entries[0].same_attachments(&attachments));
+    }
+}
@@ -0,0 +1,272 @@
+use std::path::Path;
+
+use toml::{Table, Value};
+
+use super::archive::{list_extract_entries, read_file};
+
+/// 压缩包根目录中的提交清单文件名
+pub const MANIFEST_JSON: &str = "MANIFEST.json";
+pub const MANIFEST_TOML: &str = "MANIFEST.toml";
+/// 增量提交时记录删除的文件
+pub const DELETED_FILE: &str = "DELETED.txt";
+
+/// 清单中记录的一个文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestFile {
+    /// 压缩包中的路径 unix风格
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// 提交清单，记录提交人、课程、生成时间以及每个文件的大小和 SHA-256
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    pub user_name: String,
+    pub class_name: String,
+    pub courses_number: Option<i64>,
+    pub course_name: Option<String>,
+    /// RFC 3339 格式的生成时间，可复现的压缩包中不记录
+    pub created_at: Option<String>,
+    /// 生成清单的工具及版本
+    pub tool: String,
+    pub files: Vec<ManifestFile>,
+    /// 增量提交时作为基准的上一次提交的压缩包名称
+    pub base: Option<String>,
+    /// 增量提交时自上一次提交以来未修改、没有放入压缩包的文件
+    pub unchanged: Vec<ManifestFile>,
+    /// 只压缩 git 跟踪的文件时 HEAD 的提交，包含未提交的修改时以 `-dirty` 结尾
+    pub commit: Option<String>,
+    /// 压缩包中所有内容所在的顶层文件夹，`files` 中的路径相对于这个文件夹
+    pub root: Option<String>,
+}
+
+impl Manifest {
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        table.insert(
+            "user_name".to_string(),
+            Value::from(self.user_name.as_str()),
+        );
+        table.insert(
+            "class_name".to_string(),
+            Value::from(self.class_name.as_str()),
+        );
+        if let Some(courses_number) = self.courses_number {
+            table.insert("courses_number".to_string(), Value::from(courses_number));
+        }
+        if let Some(course_name) = &self.course_name {
+            table.insert("course_name".to_string(), Value::from(course_name.as_str()));
+        }
+        if let Some(created_at) = &self.created_at {
+            table.insert("created_at".to_string(), Value::from(created_at.as_str()));
+        }
+        table.insert("tool".to_string(), Value::from(self.tool.as_str()));
+        if let Some(base) = &self.base {
+            table.insert("base".to_string(), Value::from(base.as_str()));
+        }
+        if let Some(commit) = &self.commit {
+            table.insert("commit".to_string(), Value::from(commit.as_str()));
+        }
+        if let Some(root) = &self.root {
+            table.insert("root".to_string(), Value::from(root.as_str()));
+        }
+        table.insert("files".to_string(), files_to_value(&self.files));
+        if !self.unchanged.is_empty() {
+            table.insert("unchanged".to_string(), files_to_value(&self.unchanged));
+        }
+        table
+    }
+
+    pub fn from_table(table: &Table) -> Result<Self, String> {
+        let files = files_from_value(table.get("files").ok_or("清单中缺少 files 字段")?)?;
+        let unchanged = match table.get("unchanged") {
+            Some(value) => files_from_value(value)?,
+            None => Vec::new(),
+        };
+        Ok(Manifest {
+            user_name: get_str(table, "user_name").unwrap_or_default(),
+            class_name: get_str(table, "class_name").unwrap_or_default(),
+            courses_number: table.get("courses_number").and_then(|v| v.as_integer()),
+            course_name: get_str(table, "course_name"),
+            created_at: get_str(table, "created_at"),
+            tool: get_str(table, "tool").unwrap_or_default(),
+            files,
+            base: get_str(table, "base"),
+            unchanged,
+            commit: get_str(table, "commit"),
+            root: get_str(table, "root"),
+        })
+    }
+
+    /// 提交时文件夹中的全部文件，增量提交时包括未修改的文件
+    pub fn tree(&self) -> impl Iterator<Item = &ManifestFile> {
+        self.files.iter().chain(self.unchanged.iter())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_table()).unwrap()
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(&self.to_table()).unwrap()
+    }
+
+    /// 压缩包中的路径去掉顶层文件夹，得到相对于清单的路径
+    /// 不在顶层文件夹中的条目返回 `None`
+    pub fn relative_path<'a>(&self, name: &'a str) -> Option<&'a str> {
+        match &self.root {
+            Some(root) => name.strip_prefix(root.as_str())?.strip_prefix('/'),
+            None => Some(name),
+        }
+    }
+
+    /// 根据清单文件名解析 json 或 toml 格式的清单
+    pub fn parse(file_name: &str, content: &[u8]) -> Result<Self, String> {
+        let content = std::str::from_utf8(content).map_err(|err| err.to_string())?;
+        let table: Table = if file_name.ends_with(".toml") {
+            toml::from_str(content).map_err(|err| err.to_string())?
+        } else {
+            serde_json::from_str(content).map_err(|err| err.to_string())?
+        };
+        Manifest::from_table(&table)
+    }
+}
+
+/// 在压缩包的条目中查找清单文件，清单位于压缩包根目录，
+/// 或者所有内容都放在一个顶层文件夹中时位于这个文件夹，根目录和 json 优先
+pub fn find_manifest<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    names
+        .into_iter()
+        .filter(|name| {
+            let (dir, file) = name.rsplit_once('/').unwrap_or(("", name));
+            !dir.contains('/') && (file == MANIFEST_JSON || file == MANIFEST_TOML)
+        })
+        .min_by_key(|name| (name.contains('/'), name.ends_with(".toml")))
+}
+
+/// 读取并解析压缩包中的清单文件，返回 (清单在压缩包中的路径, 清单)
+pub fn read_manifest_file(
+    archive: &Path,
+    password: &str,
+) -> Result<Option<(String, Manifest)>, String> {
+    let entries = list_extract_entries(archive, password).map_err(|err| err.to_string())?;
+    let Some(name) = find_manifest(
+        entries
+            .iter()
+            .filter(|entry| !entry.is_dir)
+            .map(|entry| entry.name.as_str()),
+    ) else {
+        return Ok(None);
+    };
+    let Some(content) = read_file(archive, password, name).map_err(|err| err.to_string())? else {
+        return Ok(None);
+    };
+    let manifest = Manifest::parse(name, &content).map_err(|err| format!("{}: {}", name, err))?;
+    Ok(Some((name.to_string(), manifest)))
+}
+
+fn get_str(table: &Table, key: &str) -> Option<String> {
+    table
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+}
+
+fn files_to_value(files: &[ManifestFile]) -> Value {
+    let files = files
+        .iter()
+        .map(|file| {
+            let mut item = Table::new();
+            item.insert("path".to_string(), Value::from(file.path.as_str()));
+            item.insert("size".to_string(), Value::from(file.size as i64));
+            item.insert("sha256".to_string(), Value::from(file.sha256.as_str()));
+            Value::Table(item)
+        })
+        .collect();
+    Value::Array(files)
+}
+
+fn files_from_value(value: &Value) -> Result<Vec<ManifestFile>, String> {
+    value
+        .as_array()
+        .ok_or("清单中的文件列表格式错误")?
+        .iter()
+        .map(|item| {
+            let item = item.as_table().ok_or("清单中的文件格式错误")?;
+            Ok(ManifestFile {
+                path: get_str(item, "path").ok_or("清单中的文件缺少 path")?,
+                size: item
+                    .get("size")
+                    .and_then(|v| v.as_integer())
+                    .ok_or("清单中的文件缺少 size")? as u64,
+                sha256: get_str(item, "sha256").ok_or("清单中的文件缺少 sha256")?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manifest_round_trip() {
+        let manifest = Manifest {
+            user_name: "张三".to_string(),
+            class_name: "CR49".to_string(),
+            courses_number: Some(13),
+            course_name: Some("C++".to_string()),
+            created_at: None,
+            tool: "cr-cli 0.1.0".to_string(),
+            files: vec![ManifestFile {
+                path: "src/main.cpp".to_string(),
+                size: 12,
+                sha256: "ab".repeat(32),
+            }],
+            base: Some("CR49_张三_20240513.zip".to_string()),
+            unchanged: vec![ManifestFile {
+                path: "笔记.md".to_string(),
+                size: 3,
+                sha256: "cd".repeat(32),
+            }],
+            commit: Some("1a2b3c4d".repeat(5)),
+            root: Some("CR49_张三_20240513".to_string()),
+        };
+        for (name, content) in [
+            (MANIFEST_JSON, manifest.to_json()),
+            (MANIFEST_TOML, manifest.to_toml()),
+        ] {
+            let parsed = Manifest::parse(name, content.as_bytes()).unwrap();
+            assert_eq!(parsed.user_name, "张三");
+            assert_eq!(parsed.courses_number, Some(13));
+            assert_eq!(parsed.created_at, None);
+            assert_eq!(parsed.files, manifest.files);
+            assert_eq!(parsed.base, manifest.base);
+            assert_eq!(parsed.commit, manifest.commit);
+            assert_eq!(parsed.root, manifest.root);
+            assert_eq!(parsed.tree().count(), 2);
+        }
+    }
+
+    #[test]
+    fn find_manifest_in_root_folder() {
+        let names = ["a/MANIFEST.toml", "a/b/MANIFEST.json", "a/MANIFEST.json"];
+        assert_eq!(find_manifest(names), Some("a/MANIFEST.json"));
+        assert_eq!(
+            find_manifest(["MANIFEST.toml", "a/MANIFEST.json"]),
+            Some("MANIFEST.toml")
+        );
+        assert_eq!(find_manifest(["a/b/MANIFEST.json"]), None);
+
+        let manifest = Manifest {
+            root: Some("a".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            manifest.relative_path("a/src/main.cpp"),
+            Some("src/main.cpp")
+        );
+        assert_eq!(manifest.relative_path("ab/main.cpp"), None);
+    }
+}
@@ -0,0 +1,26 @@
+use pulldown_cmark::{html, Options, Parser};
+
+/// 把 Markdown 渲染为邮件正文中的 HTML 文档
+pub fn to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let mut body = String::new();
+    html::push_html(&mut body, Parser::new_ext(markdown, options));
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"></head>\n<body>\n{}</body>\n</html>\n",
+        body
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_markdown_body() {
+        let html = to_html("# 13-虚函数\n\n老师好，**作业**如下:\n\n- main.cpp\n- 笔记.md\n");
+        assert!(html.contains("<h1>13-虚函数</h1>"));
+        assert!(html.contains("<strong>作业</strong>"));
+        assert!(html.contains("<li>main.cpp</li>"));
+        assert!(html.contains("<meta charset=\"utf-8\">"));
+    }
+}
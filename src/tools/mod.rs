@@ -1 +1,14 @@
-pub mod date;
\ No newline at end of file
+pub mod archive;
+pub mod compression;
+pub mod date;
+pub mod eml;
+pub mod files;
+pub mod git;
+pub mod guard;
+pub mod history;
+pub mod manifest;
+pub mod markdown;
+pub mod outbox;
+pub mod presets;
+pub mod smtp;
+pub mod zip_names;
\ No newline at end of file
@@ -0,0 +1,286 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use lettre::address::{Address, Envelope};
+use toml::{Table, Value};
+
+use super::history::HistoryEntry;
+
+/// 发送成功的邮件移动到发件箱中的这个文件夹
+const SENT_DIR: &str = "sent";
+
+/// 发件箱中一封邮件的投递信息，保存在与 `.eml` 同名的 `.toml` 文件中
+/// 邮件原文中不包含密送地址，重新发送时使用这里记录的收件人
+#[derive(Debug, Clone, Default)]
+pub struct OutboxMeta {
+    pub from: String,
+    /// 所有收件人，包括抄送和密送
+    pub to: Vec<String>,
+    pub subject: String,
+    pub message_id: Option<String>,
+    /// RFC 3339 格式的放入发件箱的时间
+    pub created_at: String,
+    /// 已经尝试发送的次数
+    pub attempts: i64,
+    pub last_attempt: Option<String>,
+    pub last_error: Option<String>,
+    /// 发送成功后写入提交历史的记录
+    pub history: Option<HistoryEntry>,
+}
+
+impl OutboxMeta {
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        table.insert("from".to_string(), Value::from(self.from.as_str()));
+        table.insert(
+            "to".to_string(),
+            Value::Array(self.to.iter().map(|to| Value::from(to.as_str())).collect()),
+        );
+        table.insert("subject".to_string(), Value::from(self.subject.as_str()));
+        if let Some(message_id) = &self.message_id {
+            table.insert("message_id".to_string(), Value::from(message_id.as_str()));
+        }
+        table.insert(
+            "created_at".to_string(),
+            Value::from(self.created_at.as_str()),
+        );
+        table.insert("attempts".to_string(), Value::from(self.attempts));
+        if let Some(last_attempt) = &self.last_attempt {
+            table.insert(
+                "last_attempt".to_string(),
+                Value::from(last_attempt.as_str()),
+            );
+        }
+        if let Some(last_error) = &self.last_error {
+            table.insert("last_error".to_string(), Value::from(last_error.as_str()));
+        }
+        if let Some(history) = &self.history {
+            table.insert("history".to_string(), Value::Table(history.to_table()));
+        }
+        table
+    }
+
+    pub fn from_table(table: &Table) -> Self {
+        let get_str = |key: &str| {
+            table
+                .get(key)
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        };
+        OutboxMeta {
+            from: get_str("from").unwrap_or_default(),
+            to: table
+                .get("to")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect(),
+            subject: get_str("subject").unwrap_or_default(),
+            message_id: get_str("message_id"),
+            created_at: get_str("created_at").unwrap_or_default(),
+            attempts: table
+                .get("attempts")
+                .and_then(|v| v.as_integer())
+                .unwrap_or(0),
+            last_attempt: get_str("last_attempt"),
+            last_error: get_str("last_error"),
+            history: table
+                .get("history")
+                .and_then(|v| v.as_table())
+                .and_then(|history| HistoryEntry::from_table(history).ok()),
+        }
+    }
+
+    /// 重新发送时使用的信封
+    pub fn envelope(&self) -> Result<Envelope, String> {
+        let parse = |address: &str| {
+            address
+                .parse::<Address>()
+                .map_err(|err| format!("无效的邮箱地址 {}: {}", address, err))
+        };
+        let from = parse(&self.from)?;
+        let to = self
+            .to
+            .iter()
+            .map(|to| parse(to))
+            .collect::<Result<Vec<Address>, String>>()?;
+        Envelope::new(Some(from), to).map_err(|err| err.to_string())
+    }
+}
+
+/// 发件箱中的一封邮件
+#[derive(Debug, Clone)]
+pub struct OutboxItem {
+    /// 文件名(不含扩展名)，用于在命令行中指定邮件
+    pub name: String,
+    pub eml: PathBuf,
+    pub meta: OutboxMeta,
+}
+
+impl OutboxItem {
+    fn meta_path(&self) -> PathBuf {
+        self.eml.with_extension("toml")
+    }
+}
+
+/// 发件箱文件夹，发送前先把邮件写入这里，发送成功后移动到 `sent/`
+pub struct Outbox {
+    pub dir: PathBuf,
+}
+
+impl Outbox {
+    pub fn new(dir: &Path) -> Self {
+        Outbox {
+            dir: dir.to_owned(),
+        }
+    }
+
+    pub fn sent_dir(&self) -> PathBuf {
+        self.dir.join(SENT_DIR)
+    }
+
+    /// 把邮件放入发件箱，文件名为放入的时间和邮件主题
+    pub fn enqueue(&self, meta: OutboxMeta, data: &[u8]) -> io::Result<OutboxItem> {
+        fs::create_dir_all(&self.dir)?;
+        let time = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let subject = meta
+            .subject
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c => c,
+            })
+            .collect::<String>();
+        let mut name = format!("{}_{}", time, subject);
+        let mut index = 1;
+        while self.dir.join(format!("{}.eml", name)).exists() {
+            index += 1;
+            name = format!("{}_{}_{}", time, subject, index);
+        }
+        let item = OutboxItem {
+            eml: self.dir.join(format!("{}.eml", name)),
+            name,
+            meta,
+        };
+        fs::write(&item.eml, data)?;
+        self.save(&item)?;
+        Ok(item)
+    }
+
+    /// 发件箱中等待发送的邮件，按放入的时间排序
+    pub fn list(&self) -> Vec<OutboxItem> {
+        let Ok(read_dir) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut items = read_dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "eml"))
+            .map(|eml| {
+                let meta = fs::read_to_string(eml.with_extension("toml"))
+                    .ok()
+                    .and_then(|content| toml::from_str::<Table>(&content).ok())
+                    .map(|table| OutboxMeta::from_table(&table))
+                    .unwrap_or_default();
+                OutboxItem {
+                    name: eml.file_stem().unwrap().to_string_lossy().to_string(),
+                    eml,
+                    meta,
+                }
+            })
+            .collect::<Vec<OutboxItem>>();
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+        items
+    }
+
+    /// 根据名称查找邮件，名称可以只写开头的一部分
+    pub fn find(&self, name: &str) -> Result<OutboxItem, String> {
+        let name = name.trim_end_matches(".eml");
+        let items = self
+            .list()
+            .into_iter()
+            .filter(|item| item.name.starts_with(name))
+            .collect::<Vec<OutboxItem>>();
+        if let Some(item) = items.iter().find(|item| item.name == name) {
+            return Ok(item.clone());
+        }
+        match items.len() {
+            0 => Err(format!("发件箱中没有 {}", name)),
+            1 => Ok(items.into_iter().next().unwrap()),
+            _ => Err(format!("{} 匹配到多封邮件，请写出更完整的名称", name)),
+        }
+    }
+
+    /// 记录一次发送失败
+    pub fn record_failure(&self, item: &mut OutboxItem, error: &str) -> io::Result<()> {
+        item.meta.attempts += 1;
+        item.meta.last_attempt = Some(chrono::Local::now().to_rfc3339());
+        item.meta.last_error = Some(error.to_string());
+        self.save(item)
+    }
+
+    /// 发送成功后把邮件和投递信息移动到 `sent/`，返回移动后的邮件路径
+    pub fn mark_sent(&self, item: &mut OutboxItem) -> io::Result<PathBuf> {
+        item.meta.attempts += 1;
+        item.meta.last_attempt = Some(chrono::Local::now().to_rfc3339());
+        item.meta.last_error = None;
+        self.save(item)?;
+        let sent_dir = self.sent_dir();
+        fs::create_dir_all(&sent_dir)?;
+        let eml = sent_dir.join(item.eml.file_name().unwrap());
+        fs::rename(item.meta_path(), eml.with_extension("toml"))?;
+        fs::rename(&item.eml, &eml)?;
+        Ok(eml)
+    }
+
+    fn save(&self, item: &OutboxItem) -> io::Result<()> {
+        fs::write(
+            item.meta_path(),
+            toml::to_string(&item.meta.to_table()).unwrap(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tools::files::TempDir;
+
+    #[test]
+    fn enqueue_retry_and_send() {
+        let temp = TempDir::new("outbox").unwrap();
+        let outbox = Outbox::new(&temp.0);
+        let meta = OutboxMeta {
+            from: "me@example.com".to_string(),
+            to: vec![
+                "teacher@example.com".to_string(),
+                "me@example.com".to_string(),
+            ],
+            subject: "CR49_张三_20240520".to_string(),
+            created_at: chrono::Local::now().to_rfc3339(),
+            ..Default::default()
+        };
+        let item = outbox
+            .enqueue(meta.clone(), b"Subject: test\r\n\r\nbody")
+            .unwrap();
+        outbox.enqueue(meta, b"Subject: test\r\n\r\nbody").unwrap();
+        assert!(item.name.ends_with("CR49_张三_20240520"));
+        assert_eq!(outbox.list().len(), 2);
+        assert!(outbox.find("2").is_err());
+
+        let mut item = outbox.find(&item.name).unwrap();
+        outbox.record_failure(&mut item, "timeout").unwrap();
+        let item = outbox.find(&item.name).unwrap();
+        assert_eq!(item.meta.attempts, 1);
+        assert_eq!(item.meta.last_error.as_deref(), Some("timeout"));
+        assert_eq!(item.meta.envelope().unwrap().to().len(), 2);
+
+        let mut item = item;
+        let sent = outbox.mark_sent(&mut item).unwrap();
+        assert!(sent.starts_with(outbox.sent_dir()));
+        assert!(sent.with_extension("toml").is_file());
+        assert_eq!(outbox.list().len(), 1);
+    }
+}
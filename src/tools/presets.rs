@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use glob::{MatchOptions, Pattern};
+
+/// 一种项目类型的构建产物排除规则
+pub struct Preset {
+    /// 预设名称，对应配置 `[zip.presets]` 中的字段名
+    pub name: &'static str,
+    /// 文件夹中存在这些文件之一时，认为是这种类型的项目
+    pub markers: &'static [&'static str],
+    /// 相对于项目文件夹的排除规则
+    pub exclude: &'static [&'static str],
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "visual_studio",
+        markers: &["*.sln", "*.vcxproj"],
+        exclude: &[
+            ".vs",
+            "Debug",
+            "Release",
+            "x64",
+            "x86",
+            "ARM64",
+            "ipch",
+            "**/*.user",
+        ],
+    },
+    Preset {
+        name: "cmake",
+        markers: &["CMakeLists.txt"],
+        exclude: &["build", "out", "cmake-build-*", ".idea", ".cache"],
+    },
+    Preset {
+        name: "cargo",
+        markers: &["Cargo.toml"],
+        exclude: &["target", ".idea"],
+    },
+    Preset {
+        name: "node",
+        markers: &["package.json"],
+        exclude: &["node_modules", "dist", ".idea"],
+    },
+    Preset {
+        name: "python",
+        markers: &["pyproject.toml", "setup.py", "requirements.txt"],
+        exclude: &[
+            "**/__pycache__",
+            "**/*.pyc",
+            ".venv",
+            "venv",
+            ".pytest_cache",
+            ".mypy_cache",
+            "*.egg-info",
+            "build",
+            "dist",
+            ".idea",
+        ],
+    },
+];
+
+/// 排除规则只匹配相对于项目文件夹的路径，`*` 不会跨越文件夹
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl Preset {
+    pub fn by_name(name: &str) -> Option<&'static Preset> {
+        PRESETS.iter().find(|preset| preset.name == name)
+    }
+
+    /// 返回匹配 `relative_path`(相对于项目文件夹 unix风格) 的排除规则
+    pub fn matches(&self, relative_path: &str) -> Option<&'static str> {
+        self.exclude.iter().copied().find(|pattern| {
+            Pattern::new(pattern)
+                .unwrap()
+                .matches_with(relative_path, MATCH_OPTIONS)
+        })
+    }
+}
+
+/// 根据文件夹中的标记文件检测项目类型，只在 `enabled` 中的预设里查找
+pub fn detect(dir: &Path, enabled: &[&'static Preset]) -> Vec<&'static Preset> {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let names = read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+    enabled
+        .iter()
+        .copied()
+        .filter(|preset| {
+            preset.markers.iter().any(|marker| {
+                let marker = Pattern::new(marker).unwrap();
+                names.iter().any(|name| marker.matches(name))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn preset_patterns() {
+        let cmake = Preset::by_name("cmake").unwrap();
+        assert_eq!(cmake.matches("build"), Some("build"));
+        assert_eq!(cmake.matches("cmake-build-debug"), Some("cmake-build-*"));
+        assert_eq!(cmake.matches("src/build"), None);
+        let python = Preset::by_name("python").unwrap();
+        assert_eq!(python.matches("__pycache__"), Some("**/__pycache__"));
+        assert_eq!(
+            python.matches("pkg/sub/__pycache__"),
+            Some("**/__pycache__")
+        );
+        assert_eq!(python.matches("main.py"), None);
+    }
+}
@@ -0,0 +1,434 @@
+use std::error::Error;
+use std::str::FromStr;
+use std::time::Duration;
+
+use lettre::address::Envelope;
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{SmtpConnection, TlsParameters};
+use lettre::transport::smtp::extension::{ClientId, Extension};
+use lettre::transport::smtp::response::Response;
+use toml::Table;
+
+/// 连接超时的默认值 包括建立连接、TLS 握手、读取欢迎信息和 EHLO
+const DEFAULT_CONNECT_TIMEOUT: u64 = 30;
+/// 之后每条命令的默认超时
+const DEFAULT_COMMAND_TIMEOUT: u64 = 60;
+
+/// `[mail].security` 连接 SMTP 服务器的加密方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    /// 连接时直接使用 TLS，通常为 465 端口
+    Tls,
+    /// 先使用明文连接，再通过 STARTTLS 升级为加密连接，通常为 587 端口
+    StartTls,
+    /// 不加密，只用于内网的中继服务器
+    None,
+}
+
+impl FromStr for Security {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tls" => Ok(Security::Tls),
+            "starttls" => Ok(Security::StartTls),
+            "none" => Ok(Security::None),
+            _ => Err(format!("未知的加密方式 {}，可选: tls starttls none", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Security {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Security::Tls => "tls",
+            Security::StartTls => "starttls",
+            Security::None => "none",
+        })
+    }
+}
+
+impl Security {
+    /// 没有配置端口时使用的默认端口
+    fn default_port(&self) -> u16 {
+        match self {
+            Security::Tls => 465,
+            Security::StartTls => 587,
+            Security::None => 25,
+        }
+    }
+}
+
+/// 连接 SMTP 服务器的配置，来自 `[mail]` 中的 smtp 相关字段
+#[derive(Debug, Clone)]
+pub struct SmtpSettings {
+    pub server: String,
+    pub port: u16,
+    pub security: Security,
+    pub connect_timeout: Duration,
+    pub command_timeout: Duration,
+    /// EHLO 中使用的名称，默认为本机的主机名
+    pub helo: ClientId,
+    /// 按顺序使用服务器支持的第一种认证方式
+    pub mechanisms: Vec<Mechanism>,
+    /// 认证使用的用户名，默认为发件邮箱
+    pub user: String,
+    /// 没有密码时不进行认证
+    pub credentials: Option<Credentials>,
+}
+
+impl SmtpSettings {
+    /// 读取 `[mail]` 中的 smtp_server smtp_port security connect_timeout timeout helo auth
+    /// 没有配置 security 时 587 端口使用 starttls，其他端口使用 tls
+    pub fn from_table(field_map: &Table) -> Result<Self, String> {
+        let get_str = |key: &str| field_map.get(key).and_then(|value| value.as_str());
+        let server = get_str("smtp_server")
+            .filter(|server| !server.is_empty())
+            .ok_or("没有填写 smtp 服务器地址 [mail].smtp_server")?
+            .to_string();
+        let port = match field_map.get("smtp_port") {
+            Some(port) => Some(
+                port.as_integer()
+                    .and_then(|port| u16::try_from(port).ok())
+                    .ok_or(format!("无效的 smtp_port: {}", port))?,
+            ),
+            None => None,
+        };
+        let security = match get_str("security") {
+            Some(security) => security.parse()?,
+            None if port == Some(587) => Security::StartTls,
+            None => Security::Tls,
+        };
+        let timeout = |key: &str, default: u64| match field_map.get(key) {
+            Some(value) => value
+                .as_integer()
+                .filter(|seconds| *seconds > 0)
+                .map(|seconds| Duration::from_secs(seconds as u64))
+                .ok_or(format!("{} 必须是正整数(秒): {}", key, value)),
+            None => Ok(Duration::from_secs(default)),
+        };
+        let helo = match get_str("helo") {
+            Some(helo) => ClientId::Domain(helo.to_string()),
+            None => ClientId::default(),
+        };
+        let mechanisms = match field_map.get("auth") {
+            None => vec![Mechanism::Plain, Mechanism::Login],
+            Some(toml::Value::Array(values)) => values
+                .iter()
+                .map(|value| parse_mechanism(value.as_str().unwrap_or_default()))
+                .collect::<Result<Vec<Mechanism>, String>>()?,
+            Some(value) => vec![parse_mechanism(value.as_str().unwrap_or_default())?],
+        };
+        let user = get_str("username")
+            .or(get_str("email"))
+            .unwrap_or_default()
+            .to_string();
+        let password = get_str("password").unwrap_or_default().to_string();
+        Ok(SmtpSettings {
+            server,
+            port: port.unwrap_or(security.default_port()),
+            security,
+            connect_timeout: timeout("connect_timeout", DEFAULT_CONNECT_TIMEOUT)?,
+            command_timeout: timeout("timeout", DEFAULT_COMMAND_TIMEOUT)?,
+            helo,
+            mechanisms,
+            credentials: (!password.is_empty()).then(|| Credentials::new(user.clone(), password)),
+            user,
+        })
+    }
+
+    /// 连接服务器，按配置协商加密并认证，返回可以直接发送邮件的连接
+    pub fn connect(&self) -> Result<SmtpConnection, Box<dyn Error>> {
+        let mut connection = self.open()?;
+        self.starttls(&mut connection)?;
+        self.authenticate(&mut connection)?;
+        Ok(connection)
+    }
+
+    /// 建立连接(tls 时同时完成 TLS 握手)，读取欢迎信息并发送 EHLO
+    pub fn open(&self) -> Result<SmtpConnection, Box<dyn Error>> {
+        let wrapper = match self.security {
+            Security::Tls => Some(TlsParameters::new(self.server.clone())?),
+            _ => None,
+        };
+        let mut connection = SmtpConnection::connect(
+            (self.server.as_str(), self.port),
+            Some(self.connect_timeout),
+            &self.helo,
+            wrapper.as_ref(),
+            None,
+        )?;
+        connection.set_timeout(Some(self.command_timeout))?;
+        Ok(connection)
+    }
+
+    /// starttls 时把连接升级为加密连接，并重新发送 EHLO，其他加密方式不做任何事
+    pub fn starttls(&self, connection: &mut SmtpConnection) -> Result<(), Box<dyn Error>> {
+        if self.security != Security::StartTls {
+            return Ok(());
+        }
+        let tls_parameters = TlsParameters::new(self.server.clone())?;
+        connection.starttls(&tls_parameters, &self.helo)?;
+        Ok(())
+    }
+
+    /// 使用服务器支持的第一种认证方式进行认证，返回使用的认证方式，没有密码时返回 `None`
+    pub fn authenticate(
+        &self,
+        connection: &mut SmtpConnection,
+    ) -> Result<Option<Mechanism>, Box<dyn Error>> {
+        let Some(credentials) = &self.credentials else {
+            return Ok(None);
+        };
+        let mechanism = connection
+            .server_info()
+            .get_auth_mechanism(&self.mechanisms);
+        connection.auth(&self.mechanisms, credentials)?;
+        Ok(mechanism)
+    }
+
+    /// 发送一封已经生成的邮件，`email` 为邮件的原始内容
+    pub fn send(&self, envelope: &Envelope, email: &[u8]) -> Result<Response, Box<dyn Error>> {
+        let mut connection = self.connect()?;
+        let response = connection.send(envelope, email)?;
+        let _ = connection.quit();
+        Ok(response)
+    }
+}
+
+/// 服务器在 EHLO 中声明的功能
+pub fn capabilities(connection: &SmtpConnection) -> Vec<String> {
+    let info = connection.server_info();
+    let mut capabilities = [
+        (Extension::StartTls, "STARTTLS"),
+        (Extension::EightBitMime, "8BITMIME"),
+        (Extension::SmtpUtfEight, "SMTPUTF8"),
+    ]
+    .into_iter()
+    .filter(|(extension, _)| info.supports_feature(*extension))
+    .map(|(_, name)| name.to_string())
+    .collect::<Vec<String>>();
+    let mechanisms = [Mechanism::Plain, Mechanism::Login, Mechanism::Xoauth2]
+        .into_iter()
+        .filter(|mechanism| info.supports_auth_mechanism(*mechanism))
+        .map(|mechanism| mechanism.to_string())
+        .collect::<Vec<String>>();
+    if !mechanisms.is_empty() {
+        capabilities.push(format!("AUTH {}", mechanisms.join(" ")));
+    }
+    capabilities
+}
+
+/// 根据常见的错误给出修改配置的提示
+pub fn hint(err: &(dyn Error + 'static), settings: &SmtpSettings) -> Option<String> {
+    let is_qq_or_163 = ["qq.com", "163.com", "126.com"]
+        .iter()
+        .any(|domain| settings.server.ends_with(domain) || settings.user.ends_with(domain));
+    let err = err.downcast_ref::<lettre::transport::smtp::Error>()?;
+    let message = err.to_string();
+    let status = err
+        .status()
+        .map(|code| code.to_string())
+        .unwrap_or_default();
+    if status == "535" || status == "534" || status == "530" {
+        return Some(match is_qq_or_163 {
+            true => "QQ/163 邮箱需要使用授权码而不是登录密码: 在网页邮箱的 设置 → 账户 中开启 SMTP 服务并生成授权码，填写到 [mail].password".to_string(),
+            false => "用户名或密码错误，部分邮箱需要使用授权码或应用专用密码，用户名不是邮箱地址时填写 [mail].username".to_string(),
+        });
+    }
+    if message.contains("No compatible authentication mechanism") {
+        return Some("服务器不支持 [mail].auth 中的认证方式，请根据服务器功能修改".to_string());
+    }
+    if err.is_timeout() {
+        return Some(format!(
+            "连接 {}:{} 超时: 检查网络，校园网可能屏蔽了 465 或 25 端口，可以尝试 587 端口和 security = \"starttls\"",
+            settings.server, settings.port
+        ));
+    }
+    if err.is_tls() {
+        return Some(
+            "TLS 握手失败: 465 端口通常使用 security = \"tls\"，587 端口使用 \"starttls\""
+                .to_string(),
+        );
+    }
+    if message.contains("refused") {
+        return Some(format!(
+            "{} 端口拒绝连接: 检查 smtp_port 以及服务器是否开启了 SMTP 服务",
+            settings.port
+        ));
+    }
+    if message.contains("lookup") || message.contains("resolve") {
+        return Some(format!(
+            "无法解析服务器地址 {}: 检查 smtp_server",
+            settings.server
+        ));
+    }
+    None
+}
+
+fn parse_mechanism(mechanism: &str) -> Result<Mechanism, String> {
+    match mechanism.to_lowercase().as_str() {
+        "plain" => Ok(Mechanism::Plain),
+        "login" => Ok(Mechanism::Login),
+        "xoauth2" => Ok(Mechanism::Xoauth2),
+        _ => Err(format!(
+            "未知的认证方式 {}，可选: plain login xoauth2",
+            mechanism
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    use super::*;
+
+    /// 本地的 SMTP 替身，只接受一个连接，返回收到的所有命令
+    /// `stall` 为收到以此开头的命令后不再回复，直到客户端断开连接，用于测试超时
+    /// `auth_reply` 为认证的最终回复
+    fn fake_server(
+        stall: Option<&'static str>,
+        auth_reply: &'static [u8],
+    ) -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut lines = Vec::new();
+            writer.write_all(b"220 fake ESMTP\r\n").unwrap();
+            let mut line = String::new();
+            let mut is_stalled = false;
+            while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                let command = line.trim_end().to_string();
+                line.clear();
+                lines.push(command.clone());
+                is_stalled |= stall.is_some_and(|stall| command.starts_with(stall));
+                if is_stalled {
+                    continue;
+                }
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250-fake\r\n250-8BITMIME\r\n250 AUTH PLAIN LOGIN XOAUTH2\r\n"
+                } else if command == "AUTH LOGIN" {
+                    b"334 VXNlcm5hbWU6\r\n"
+                } else if lines.len() >= 2 && lines[lines.len() - 2] == "AUTH LOGIN" {
+                    b"334 UGFzc3dvcmQ6\r\n"
+                } else if command.starts_with("AUTH")
+                    || lines.len() >= 3 && lines[lines.len() - 3] == "AUTH LOGIN"
+                {
+                    auth_reply
+                } else if command == "DATA" {
+                    writer.write_all(b"354 go ahead\r\n").unwrap();
+                    // 读取邮件内容直到单独一行的 `.`
+                    while reader.read_line(&mut line).unwrap() > 0 {
+                        let done = line == ".\r\n";
+                        line.clear();
+                        if done {
+                            break;
+                        }
+                    }
+                    b"250 queued\r\n"
+                } else if command == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                writer.write_all(reply).unwrap();
+            }
+            lines
+        });
+        (port, handle)
+    }
+
+    fn settings(port: u16, extra: &str) -> SmtpSettings {
+        let table = toml::from_str::<Table>(&format!(
+            "smtp_server = \"127.0.0.1\"\nsmtp_port = {}\nsecurity = \"none\"\n\
+             email = \"me@example.com\"\npassword = \"secret\"\n{}",
+            port, extra
+        ))
+        .unwrap();
+        SmtpSettings::from_table(&table).unwrap()
+    }
+
+    fn message() -> lettre::Message {
+        lettre::Message::builder()
+            .from("me@example.com".parse().unwrap())
+            .to("teacher@example.com".parse().unwrap())
+            .bcc("me@example.com".parse().unwrap())
+            .subject("CR49_张三_20240520")
+            .body("作业".to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn send_through_fake_server() {
+        let (port, server) = fake_server(None, b"235 ok\r\n");
+        let settings = settings(port, "helo = \"lab-pc\"\nauth = \"plain\"");
+        let message = message();
+        settings
+            .send(message.envelope(), &message.formatted())
+            .unwrap();
+        let lines = server.join().unwrap();
+        assert_eq!(lines[0], "EHLO lab-pc");
+        // base64("\0me@example.com\0secret")
+        assert_eq!(lines[1], "AUTH PLAIN AG1lQGV4YW1wbGUuY29tAHNlY3JldA==");
+        assert!(lines.contains(&"RCPT TO:<teacher@example.com>".to_string()));
+        assert!(lines.contains(&"RCPT TO:<me@example.com>".to_string()));
+        assert_eq!(lines.last().unwrap(), "QUIT");
+    }
+
+    #[test]
+    fn login_and_command_timeout() {
+        let (port, server) = fake_server(Some("MAIL FROM"), b"235 ok\r\n");
+        let settings = settings(port, "auth = [\"login\"]\ntimeout = 1");
+        assert_eq!(settings.security, Security::None);
+        assert_eq!(settings.command_timeout, Duration::from_secs(1));
+        let message = message();
+        let started = std::time::Instant::now();
+        let result = settings.send(message.envelope(), &message.formatted());
+        assert!(result.is_err());
+        // 超时后客户端发送 QUIT 同样等待超时再断开连接
+        assert!(started.elapsed() < Duration::from_secs(4));
+        let lines = server.join().unwrap();
+        assert_eq!(lines[1], "AUTH LOGIN");
+        assert!(lines.iter().any(|line| line.starts_with("MAIL FROM")));
+        assert!(!lines.iter().any(|line| line.starts_with("RCPT")));
+    }
+
+    #[test]
+    fn security_defaults_to_port() {
+        let table =
+            toml::from_str::<Table>("smtp_server = \"smtp.qq.com\"\nsmtp_port = 587").unwrap();
+        let settings = SmtpSettings::from_table(&table).unwrap();
+        assert_eq!(settings.security, Security::StartTls);
+        assert!(settings.credentials.is_none());
+        let table =
+            toml::from_str::<Table>("smtp_server = \"smtp.qq.com\"\nauth = \"cram-md5\"").unwrap();
+        assert!(SmtpSettings::from_table(&table).is_err());
+    }
+
+    #[test]
+    fn check_steps_and_auth_hint() {
+        let (port, server) = fake_server(None, b"535 Login fail\r\n");
+        let mut settings = settings(port, "");
+        let mut connection = settings.open().unwrap();
+        assert_eq!(
+            capabilities(&connection),
+            ["8BITMIME", "AUTH PLAIN LOGIN XOAUTH2"]
+        );
+        settings.starttls(&mut connection).unwrap();
+        let err = settings.authenticate(&mut connection).unwrap_err();
+        drop(connection);
+        server.join().unwrap();
+        assert!(hint(err.as_ref(), &settings)
+            .unwrap()
+            .contains("用户名或密码错误"));
+        settings.user = "123456@qq.com".to_string();
+        assert!(hint(err.as_ref(), &settings).unwrap().contains("授权码"));
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    str::FromStr,
+};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+/// Info-ZIP Unicode Path 扩展字段
+const UNICODE_PATH_EXTRA_ID: u16 = 0x7075;
+/// 通用标记中表示文件名为 UTF-8 的位
+const UTF8_FLAG: u16 = 1 << 11;
+const LOCAL_HEADER_LEN: usize = 30;
+const CENTRAL_HEADER_LEN: usize = 46;
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+
+/// 写入 zip 时文件名的编码，对应配置 `[zip].filename_encoding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilenameEncoding {
+    /// UTF-8 文件名并设置 UTF-8 标记
+    Utf8,
+    /// `utf8+extra`: GBK 文件名，同时在 Unicode Path 扩展字段中写入 UTF-8 文件名
+    GbkUnicodePath,
+    /// 不带 UTF-8 标记的 GBK 文件名，中文 Windows 上的旧版解压工具可以直接识别
+    Gbk,
+}
+
+impl FromStr for FilenameEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utf8" => Ok(FilenameEncoding::Utf8),
+            // `gbk+unicode-path` 是 `utf8+extra` 的别名，更直接地描述了写入的内容
+            "utf8+extra" | "gbk+unicode-path" => Ok(FilenameEncoding::GbkUnicodePath),
+            "gbk" => Ok(FilenameEncoding::Gbk),
+            _ => Err(format!(
+                "未知的文件名编码 {}，可选: utf8 utf8+extra gbk",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for FilenameEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilenameEncoding::Utf8 => write!(f, "utf8"),
+            FilenameEncoding::GbkUnicodePath => write!(f, "utf8+extra"),
+            FilenameEncoding::Gbk => write!(f, "gbk"),
+        }
+    }
+}
+
+impl FilenameEncoding {
+    /// 文件名无法使用这种编码时返回 `true`，这些文件名会保留为带 UTF-8 标记的文件名
+    pub fn is_unencodable(&self, name: &str) -> bool {
+        *self != FilenameEncoding::Utf8 && encode_gbk(name).is_none()
+    }
+}
+
+fn encode_gbk(name: &str) -> Option<Vec<u8>> {
+    let (encoded, _, had_unmappable) = encoding_rs::GBK.encode(name);
+    (!had_unmappable).then(|| encoded.into_owned())
+}
+
+/// 读取时文件名的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameSource {
+    Ascii,
+    /// 设置了 UTF-8 标记
+    Utf8Flag,
+    /// Info-ZIP Unicode Path 扩展字段
+    UnicodePath,
+    /// 没有 UTF-8 标记但内容是合法的 UTF-8
+    Utf8,
+    /// 按 GBK/GB18030 解码
+    Gbk,
+    /// 无法识别 按 CP437 处理
+    Cp437,
+}
+
+impl std::fmt::Display for NameSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NameSource::Ascii => write!(f, "ASCII"),
+            NameSource::Utf8Flag => write!(f, "UTF-8 标记"),
+            NameSource::UnicodePath => write!(f, "Unicode Path 扩展字段"),
+            NameSource::Utf8 => write!(f, "UTF-8(无标记)"),
+            NameSource::Gbk => write!(f, "GBK"),
+            NameSource::Cp437 => write!(f, "CP437"),
+        }
+    }
+}
+
+/// 中央目录中的一个条目
+pub struct CentralEntry {
+    /// 中央目录记录的固定部分
+    header: [u8; CENTRAL_HEADER_LEN],
+    /// 文件名的原始内容
+    pub name_raw: Vec<u8>,
+    extra: Vec<u8>,
+    comment: Vec<u8>,
+}
+
+impl CentralEntry {
+    fn flags(&self) -> u16 {
+        read_u16(&self.header, 8)
+    }
+
+    fn compressed_size(&self) -> u32 {
+        read_u32(&self.header, 20)
+    }
+
+    fn local_header_offset(&self) -> u32 {
+        read_u32(&self.header, 42)
+    }
+
+    /// Unicode Path 扩展字段中的文件名，字段中记录的 CRC 与文件名不一致时忽略
+    fn unicode_path(&self) -> Option<String> {
+        let mut extra = self.extra.as_slice();
+        while extra.len() >= 4 {
+            let id = read_u16(extra, 0);
+            let len = read_u16(extra, 2) as usize;
+            let data = extra.get(4..4 + len)?;
+            if id == UNICODE_PATH_EXTRA_ID && data.len() > 5 && data[0] == 1 {
+                if read_u32(data, 1) != crc32fast::hash(&self.name_raw) {
+                    return None;
+                }
+                return String::from_utf8(data[5..].to_vec()).ok();
+            }
+            extra = &extra[4 + len..];
+        }
+        None
+    }
+
+    /// 按照 `cr-cli unzip` 的规则读取文件名，返回文件名和它的来源
+    pub fn read_name(&self) -> (String, NameSource) {
+        let raw = &self.name_raw;
+        if raw.is_ascii() {
+            return (String::from_utf8_lossy(raw).to_string(), NameSource::Ascii);
+        }
+        if self.flags() & UTF8_FLAG != 0 {
+            let name = String::from_utf8_lossy(raw).to_string();
+            return (name, NameSource::Utf8Flag);
+        }
+        if let Some(name) = self.unicode_path() {
+            return (name, NameSource::UnicodePath);
+        }
+        if let Ok(name) = std::str::from_utf8(raw) {
+            return (name.to_string(), NameSource::Utf8);
+        }
+        let (decoded, _, had_errors) = encoding_rs::GB18030.decode(raw);
+        match had_errors {
+            true => (String::from_utf8_lossy(raw).to_string(), NameSource::Cp437),
+            false => (decoded.into_owned(), NameSource::Gbk),
+        }
+    }
+
+    /// 不识别 UTF-8 标记和扩展字段的旧版工具在中文 Windows 上显示的文件名
+    pub fn legacy_name(&self) -> String {
+        encoding_rs::GBK.decode(&self.name_raw).0.into_owned()
+    }
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn write_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// 读取中央目录，返回全部条目和压缩包注释，不支持 zip64
+pub fn read_central_directory(path: &Path) -> Result<(Vec<CentralEntry>, Vec<u8>), Box<dyn Error>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let file_len = file.seek(SeekFrom::End(0))?;
+    // 目录结束记录在文件末尾 之后最多有 65535 字节的注释
+    let tail_len = file_len.min((END_OF_CENTRAL_DIRECTORY_LEN + u16::MAX as usize) as u64);
+    file.seek(SeekFrom::Start(file_len - tail_len))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let signature = END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes();
+    let end = (0..=tail.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_LEN))
+        .rev()
+        .find(|&i| tail[i..i + 4] == signature)
+        .ok_or_else(|| invalid_data("不是 zip 压缩包"))?;
+    let end_record = &tail[end..];
+    let entry_count = read_u16(end_record, 10);
+    let directory_offset = read_u32(end_record, 16);
+    if entry_count == u16::MAX || directory_offset == u32::MAX {
+        return Err(invalid_data("不支持 zip64 压缩包").into());
+    }
+    let comment_len = read_u16(end_record, 20) as usize;
+    let comment = end_record
+        .get(END_OF_CENTRAL_DIRECTORY_LEN..END_OF_CENTRAL_DIRECTORY_LEN + comment_len)
+        .unwrap_or_default()
+        .to_vec();
+
+    file.seek(SeekFrom::Start(directory_offset as u64))?;
+    let mut entries = Vec::new();
+    for _ in 0..entry_count {
+        let mut header = [0u8; CENTRAL_HEADER_LEN];
+        file.read_exact(&mut header)?;
+        if read_u32(&header, 0) != CENTRAL_HEADER_SIGNATURE {
+            return Err(invalid_data("中央目录损坏").into());
+        }
+        let mut read_field = |offset: usize| -> io::Result<Vec<u8>> {
+            let mut field = vec![0u8; read_u16(&header, offset) as usize];
+            file.read_exact(&mut field)?;
+            Ok(field)
+        };
+        let name_raw = read_field(28)?;
+        let extra = read_field(30)?;
+        let comment = read_field(32)?;
+        entries.push(CentralEntry {
+            header,
+            name_raw,
+            extra,
+            comment,
+        });
+    }
+    Ok((entries, comment))
+}
+
+/// 重写文件名只支持普通的 zip 压缩包，在压缩之前检查，避免压缩完成后才发现需要 zip64
+/// `total_size` 为压缩前的大小，压缩后不会超过它太多，超过 4GB 时同样拒绝
+pub fn check_without_zip64(
+    encoding: FilenameEncoding,
+    entry_count: usize,
+    total_size: u64,
+) -> Result<(), String> {
+    if encoding == FilenameEncoding::Utf8 {
+        return Ok(());
+    }
+    if entry_count >= u16::MAX as usize || total_size >= u32::MAX as u64 {
+        return Err(format!(
+            "超过 65535 个文件或者 4GB 的压缩包需要 zip64，filename_encoding 为 {} 时不支持，请改为 utf8",
+            encoding
+        ));
+    }
+    Ok(())
+}
+
+/// 按照指定的编码重写 zip 压缩包中的文件名，无法编码的文件名保留为 UTF-8
+pub fn rewrite_names(path: &Path, encoding: FilenameEncoding) -> Result<(), Box<dyn Error>> {
+    if encoding == FilenameEncoding::Utf8 {
+        return Ok(());
+    }
+    let (entries, comment) = read_central_directory(path)?;
+    let entry_count = entries.len() as u16;
+    let rewritten_path = path.with_extension("names");
+    let mut source = BufReader::new(File::open(path)?);
+    let mut target = BufWriter::new(File::create(&rewritten_path)?);
+    let mut directory = Vec::new();
+    let mut offset = 0u64;
+    for entry in entries {
+        let name = String::from_utf8_lossy(&entry.name_raw).to_string();
+        let (name_raw, flags, unicode_path) = match encode_gbk(&name) {
+            Some(encoded) if !name.is_ascii() => {
+                let unicode_path = match encoding {
+                    FilenameEncoding::GbkUnicodePath => {
+                        let mut field = Vec::new();
+                        field.extend_from_slice(&UNICODE_PATH_EXTRA_ID.to_le_bytes());
+                        field.extend_from_slice(&(5 + name.len() as u16).to_le_bytes());
+                        field.push(1);
+                        field.extend_from_slice(&crc32fast::hash(&encoded).to_le_bytes());
+                        field.extend_from_slice(name.as_bytes());
+                        field
+                    }
+                    _ => Vec::new(),
+                };
+                (encoded, entry.flags() & !UTF8_FLAG, unicode_path)
+            }
+            _ => (entry.name_raw.clone(), entry.flags(), Vec::new()),
+        };
+
+        // 本地文件头
+        let local_offset = entry.local_header_offset();
+        if local_offset == u32::MAX || entry.compressed_size() == u32::MAX {
+            return Err(invalid_data("不支持 zip64 压缩包").into());
+        }
+        source.seek(SeekFrom::Start(local_offset as u64))?;
+        let mut local_header = [0u8; LOCAL_HEADER_LEN];
+        source.read_exact(&mut local_header)?;
+        if read_u32(&local_header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid_data("本地文件头损坏").into());
+        }
+        let mut local_name = vec![0u8; read_u16(&local_header, 26) as usize];
+        source.read_exact(&mut local_name)?;
+        let mut local_extra = vec![0u8; read_u16(&local_header, 28) as usize];
+        source.read_exact(&mut local_extra)?;
+        local_extra.extend_from_slice(&unicode_path);
+        write_u16(&mut local_header, 6, flags);
+        write_u16(&mut local_header, 26, name_raw.len() as u16);
+        write_u16(&mut local_header, 28, local_extra.len() as u16);
+        target.write_all(&local_header)?;
+        target.write_all(&name_raw)?;
+        target.write_all(&local_extra)?;
+
+        // 压缩后的数据以及数据描述符
+        let mut data_len = entry.compressed_size() as u64;
+        io::copy(&mut (&mut source).take(data_len), &mut target)?;
+        if flags & (1 << 3) != 0 {
+            let mut descriptor = [0u8; 16];
+            source.read_exact(&mut descriptor[..12])?;
+            let descriptor_len = match read_u32(&descriptor, 0) == DATA_DESCRIPTOR_SIGNATURE {
+                true => {
+                    source.read_exact(&mut descriptor[12..])?;
+                    16
+                }
+                false => 12,
+            };
+            target.write_all(&descriptor[..descriptor_len])?;
+            data_len += descriptor_len as u64;
+        }
+
+        // 中央目录记录
+        let mut header = entry.header;
+        let mut extra = entry.extra;
+        extra.extend_from_slice(&unicode_path);
+        write_u16(&mut header, 8, flags);
+        write_u16(&mut header, 28, name_raw.len() as u16);
+        write_u16(&mut header, 30, extra.len() as u16);
+        write_u32(&mut header, 42, offset as u32);
+        directory.extend_from_slice(&header);
+        directory.extend_from_slice(&name_raw);
+        directory.extend_from_slice(&extra);
+        directory.extend_from_slice(&entry.comment);
+
+        offset += (LOCAL_HEADER_LEN + name_raw.len() + local_extra.len()) as u64 + data_len;
+    }
+    if offset + directory.len() as u64 >= u32::MAX as u64 {
+        return Err(invalid_data("不支持 zip64 压缩包").into());
+    }
+    let mut end_record = [0u8; END_OF_CENTRAL_DIRECTORY_LEN];
+    write_u32(&mut end_record, 0, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+    write_u16(&mut end_record, 8, entry_count);
+    write_u16(&mut end_record, 10, entry_count);
+    write_u32(&mut end_record, 12, directory.len() as u32);
+    write_u32(&mut end_record, 16, offset as u32);
+    write_u16(&mut end_record, 20, comment.len() as u16);
+    target.write_all(&directory)?;
+    target.write_all(&end_record)?;
+    target.write_all(&comment)?;
+    target.flush()?;
+    drop(target);
+    std::fs::rename(&rewritten_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rewrite_and_read_names() {
+        let names = ["a.txt", "作业/说明.txt", "😀.txt"];
+        for (encoding, source) in [
+            (FilenameEncoding::GbkUnicodePath, NameSource::UnicodePath),
+            (FilenameEncoding::Gbk, NameSource::Gbk),
+        ] {
+            let path = std::env::temp_dir().join(format!("cr-cli-names-{:?}.zip", encoding));
+            let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+            for name in names {
+                writer
+                    .start_file(name, zip::write::SimpleFileOptions::default())
+                    .unwrap();
+                writer.write_all(name.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+            rewrite_names(&path, encoding).unwrap();
+
+            let (entries, _) = read_central_directory(&path).unwrap();
+            let read_names = entries.iter().map(|e| e.read_name()).collect::<Vec<_>>();
+            assert_eq!(read_names[0], ("a.txt".to_string(), NameSource::Ascii));
+            assert_eq!(read_names[1], ("作业/说明.txt".to_string(), source));
+            assert_eq!(entries[1].legacy_name(), "作业/说明.txt");
+            // 无法使用 GBK 编码的文件名保留 UTF-8 标记
+            assert_eq!(read_names[2], ("😀.txt".to_string(), NameSource::Utf8Flag));
+            // 重写后的压缩包仍然可以正常解压
+            let mut archive = zip::ZipArchive::new(File::open(&path).unwrap()).unwrap();
+            for (index, name) in names.iter().enumerate() {
+                let mut content = String::new();
+                archive
+                    .by_index(index)
+                    .unwrap()
+                    .read_to_string(&mut content)
+                    .unwrap();
+                assert_eq!(&content, name);
+            }
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn parse_encoding_and_check_zip64() {
+        let encoding = "utf8+extra".parse::<FilenameEncoding>().unwrap();
+        assert_eq!(encoding, FilenameEncoding::GbkUnicodePath);
+        assert_eq!(encoding.to_string(), "utf8+extra");
+        assert_eq!("gbk+unicode-path".parse::<FilenameEncoding>(), Ok(encoding));
+        assert!("utf16".parse::<FilenameEncoding>().is_err());
+
+        assert!(check_without_zip64(encoding, 10, 1024).is_ok());
+        assert!(check_without_zip64(encoding, 70000, 1024).is_err());
+        assert!(check_without_zip64(FilenameEncoding::Gbk, 10, 5 << 30).is_err());
+        assert!(check_without_zip64(FilenameEncoding::Utf8, 70000, 5 << 30).is_ok());
+    }
+}